@@ -1,6 +1,7 @@
-use crate::nix_ext as nix;
+use crate::{nix_ext as nix, theme::ThemeName, tui::Align};
 use clap::{Args, Parser, Subcommand};
-use std::{path::PathBuf, str::FromStr};
+use regex::Regex;
+use std::{env, fmt, path::PathBuf, str::FromStr, time::Duration};
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct NiceLevel(i32);
@@ -18,6 +19,11 @@ impl NiceLevel {
     pub const fn get(&self) -> i32 {
         self.0
     }
+
+    /// The weight that CFS gives to a task at this nice level
+    pub const fn weight(&self) -> u32 {
+        nix::nice_weight(self.0)
+    }
 }
 
 impl FromStr for NiceLevel {
@@ -32,34 +38,362 @@ impl FromStr for NiceLevel {
     }
 }
 
+/// The path of the testnice that is running, which is the default for
+/// `--this`, or where `build.sh` installs it if we can't tell
+fn running_testnice() -> PathBuf {
+    env::current_exe().unwrap_or_else(|_| PathBuf::from("/usr/local/bin/testnice"))
+}
+
+/// The percentage of time that a flood thread spends working
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DutyCycle(u32);
+
+impl DutyCycle {
+    /// Construct a new duty cycle, bounds checking that this is a valid
+    /// percentage of time to be busy
+    pub const fn new(percent: u32) -> Option<Self> {
+        if percent > 0 && percent <= 100 {
+            Some(Self(percent))
+        } else {
+            None
+        }
+    }
+
+    /// How long to sleep after being busy for `busy`, so that we are busy for
+    /// this percentage of the time overall
+    pub fn idle_time(&self, busy: Duration) -> Duration {
+        busy * (100 - self.0) / self.0
+    }
+
+    /// This percentage as a fraction, e.g. 0.3 for 30%
+    pub fn fraction(&self) -> f64 {
+        self.0 as f64 / 100.0
+    }
+}
+
+impl FromStr for DutyCycle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let percent = s.strip_suffix('%').unwrap_or(s);
+        let inner = percent
+            .parse()
+            .map_err(|_| String::from("not a percentage"))?;
+        match Self::new(inner) {
+            Some(duty_cycle) => Ok(duty_cycle),
+            None => Err(String::from("duty cycle must be between 1% and 100%")),
+        }
+    }
+}
+
+impl fmt::Display for DutyCycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}%", self.0)
+    }
+}
+
+/// A set of CPUs, written like the kernel's CPU lists (e.g. `0,2-3`)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CpuList(Vec<usize>);
+
+impl CpuList {
+    pub fn get(&self) -> &[usize] {
+        &self.0
+    }
+}
+
+impl FromStr for CpuList {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match nix::parse_cpu_list(s) {
+            Some(cpus) if !cpus.is_empty() => Ok(Self(cpus)),
+            Some(..) => Err(String::from("no CPUs given")),
+            None => Err(String::from("expected a list of CPUs like `0,2-3`")),
+        }
+    }
+}
+
+impl fmt::Display for CpuList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cpus = self.0.iter().map(|cpu| cpu.to_string()).collect::<Vec<_>>();
+        write!(f, "{}", cpus.join(","))
+    }
+}
+
+/// The work done in each step of a computation. These are ordered from
+/// cheapest to most expensive. The costs are rough figures for a modern x86
+/// CPU in a release build.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Compute {
+    /// Nothing but the loop itself, about 0.4ns per step
+    #[default]
+    Noop,
+    /// A xorshift step on an integer, about 2ns per step
+    Int,
+    /// A multiply-add on a float, about 4ns per step
+    Float,
+    /// Both of the above, plus an integer division, about 15ns per step
+    Mixed,
+    /// A multiply-add on each of 8 independent floats, which is 16
+    /// floating-point operations per step. These are vectorized but
+    /// latency-bound, about 4ns per step
+    MulAdd,
+}
+
+impl Compute {
+    /// The number of floating-point operations in each step of
+    /// [`Compute::MulAdd`]. Its help says this too, since rustdoc links
+    /// aren't resolved there.
+    pub const FLOPS_PER_STEP: usize = 16;
+}
+
+fn parse_gflops(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(gflops) if gflops.is_finite() && gflops > 0.0 => Ok(gflops),
+        Ok(..) => Err(String::from("must be a positive number")),
+        Err(..) => Err(String::from("not a number")),
+    }
+}
+
+fn parse_thread_count(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(0) => Err(String::from("must be at least 1")),
+        Ok(thread_count) => Ok(thread_count),
+        Err(..) => Err(String::from("not a whole number of threads")),
+    }
+}
+
+/// Refreshing faster than this would keep the tui busy
+const MIN_REFRESH_MS: u64 = 10;
+
+fn parse_refresh_ms(s: &str) -> Result<u64, String> {
+    match s.parse::<u64>() {
+        Ok(ms) if ms >= MIN_REFRESH_MS => Ok(ms),
+        Ok(..) => Err(format!("must be at least {MIN_REFRESH_MS}")),
+        Err(..) => Err(String::from("not a whole number of milliseconds")),
+    }
+}
+
+/// How to print the results of a subcommand
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Aligned plain text
+    #[default]
+    Text,
+    /// GitHub-flavored Markdown tables, for pasting into issues and notes
+    Markdown,
+}
+
+/// How to print a [`nix::Sched`] for other programs to read
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DumpFormat {
+    /// A single JSON object per sample
+    #[default]
+    Json,
+    /// A header row followed by one row per sample
+    Csv,
+}
+
 #[derive(Args, Clone)]
 pub struct FloodCommand {
     /// The nice level for the parent process
     #[arg(long)]
     pub ni: NiceLevel,
-    /// The number of threads to create
-    ///
-    /// # Todo
-    /// Allow users to not specify this and use the main thread instead of
-    /// spawning a single thread
-    #[arg(long, short)]
-    pub thread_count: usize,
+    /// If we aren't allowed to set `--ni` (e.g. a negative nice level without
+    /// CAP_SYS_NICE), warn and carry on at the nice level we have, instead of
+    /// failing
+    #[arg(long)]
+    pub best_effort: bool,
+    /// The number of threads to do the work on. Without this (or with 1), the
+    /// work is done on the main thread, which is what /proc/[pid]/sched
+    /// describes. With more, the main thread only waits for the others
+    #[arg(long, short, value_parser = parse_thread_count)]
+    pub thread_count: Option<usize>,
     /// The number of steps in each computation
     #[arg(long, short)]
     pub steps: Option<usize>,
+    /// The work done in each step of a computation
+    #[arg(long, value_enum, default_value_t)]
+    pub compute: Compute,
+    /// Do about this many billion floating-point operations in each
+    /// computation (e.g. `0.5`), using the `mul-add` kernel. This is a unit
+    /// that means the same thing across machines, unlike `--steps`. The
+    /// achieved GFLOP/s is printed when the flood finishes.
+    #[arg(long, value_name = "N", conflicts_with_all = ["steps", "compute"], value_parser = parse_gflops)]
+    pub gflops: Option<f64>,
     /// The logfile to be used This defaults to /dev/null
     #[arg(long, default_value = "/dev/null")]
     pub logfile: PathBuf,
+    /// Cycle through each of the CPUs that we are allowed to run on, pinning
+    /// to a single CPU for this long at a time (e.g. `500ms`). This makes
+    /// `se.nr_migrations` climb at a steady rate. The interval is only checked
+    /// between computations, so it is rounded up to a whole computation.
+    #[arg(long)]
+    pub affinity_sweep: Option<humantime::Duration>,
+    /// The percentage of time to spend working (e.g. `30%`). After each
+    /// computation we sleep for long enough to keep to this, so that the
+    /// process looks more like a real, partially-busy task. Defaults to 100%
+    #[arg(long)]
+    pub duty_cycle: Option<DutyCycle>,
+    /// The percentage of a CPU to use (e.g. `40%`). Unlike `--duty-cycle`,
+    /// this measures how much CPU time we actually got and sleeps to make up
+    /// the difference, so it holds steady regardless of machine speed or
+    /// `--steps`. We can still get less than this if we are competing for the
+    /// CPU.
+    #[arg(long, conflicts_with = "duty_cycle")]
+    pub target_cpu: Option<DutyCycle>,
+    /// The timer slack in nanoseconds, which is how late our sleeps are
+    /// allowed to wake up. Lowering this makes `--duty-cycle` and
+    /// `--target-cpu` more precise. 0 means the default (normally 50us)
+    #[arg(long, value_name = "NS")]
+    pub timer_slack: Option<u64>,
+    /// Set `SCHED_RESET_ON_FORK`, so that anything this process forks does not
+    /// inherit a real-time policy or a negative nice level
+    #[arg(long)]
+    pub reset_on_fork: bool,
+    /// When the flood finishes, print a JSON summary of the run (iterations,
+    /// elapsed time and iterations per second) to stdout, for feeding into
+    /// benchmarking scripts. Notes and warnings go to stderr instead, so
+    /// that stdout is only the JSON
+    #[arg(long)]
+    pub bench_json: bool,
+    /// Log to `<LOGFILE>.<pid>` instead of the shared logfile. Nothing else
+    /// writes to this file, so we don't need to lock it for every completion.
+    /// `stats` reads these files along with the shared logfile.
+    #[arg(long)]
+    pub per_process_log: bool,
+    /// Log each completion as a line of JSON with the pid, timestamp and
+    /// `se.vruntime` (e.g. `{"pid":123,"ts":4133589775111,"vruntime":93.7}`)
+    /// instead of the compact format, so that the logfile can be tailed into
+    /// a log pipeline. The tui and `stats` still read it.
+    #[arg(long)]
+    pub json_lines: bool,
+    /// Write each thread's completions to the logfile this many at a time, or
+    /// once the first has waited for 100ms, so that the threads don't wait on
+    /// each other for the lock as often. Completions still have the time that
+    /// they happened at, but show up in the tui up to 100ms later.
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub log_batch: usize,
+    /// The scheduling policy to run under. By default this is inherited,
+    /// which normally means `other`
+    #[arg(long, value_enum)]
+    pub policy: Option<nix::SchedPolicy>,
+    /// The real-time priority for `--policy fifo` or `rr`, from 1 (lowest) to
+    /// 99 (highest). A real-time flood starves every normal task on its CPU,
+    /// apart from what real-time throttling leaves them (normally 5%)
+    #[arg(long, value_name = "PRIO", required_if_eq_any = [("policy", "fifo"), ("policy", "rr")])]
+    pub rt_priority: Option<i32>,
+    /// Log to a new logfile with the current time appended to `--logfile`
+    /// (e.g. `/tmp/nicelog-20240101-120000`), instead of clearing the old one
+    #[arg(long)]
+    pub unique_log: bool,
+    /// Periodically write reports about this process to this inherited file
+    /// descriptor. This is how the tui reads the sched of the processes it
+    /// spawns.
+    #[arg(long, hide = true)]
+    pub report_fd: Option<i32>,
+    /// Also spawn this many single-threaded floods at nice 19, to compete with
+    /// this one. They are stopped when this flood exits.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub noise: usize,
+    /// Run in a new cgroup with `cpu.weight.nice` set to this, which weights
+    /// the whole cgroup like a nice level. This needs cgroup v2.
+    #[arg(long, value_name = "NI")]
+    pub cgroup_weight_nice: Option<NiceLevel>,
+    /// Also set the nice level of this existing process (but not its other
+    /// threads) to `--ni`, so that it competes with the flood on equal terms
+    #[arg(
+        long,
+        value_name = "PID",
+        value_parser = clap::value_parser!(i32).range(1..),
+        allow_negative_numbers = true
+    )]
+    pub target_pid: Option<i32>,
+    /// Stop after this long (e.g. `30s`), instead of running until we are
+    /// interrupted. This is only checked between computations, so it is
+    /// rounded up to a whole computation.
+    #[arg(long)]
+    pub duration: Option<humantime::Duration>,
+    /// Stop after doing the computation exactly this many times, between all
+    /// of the threads. With `--steps`, this fixes the total amount of work, so
+    /// that runs at different nice levels can be compared by how long they
+    /// took. With `--duration` too, we stop at whichever comes first.
+    #[arg(long, value_name = "N")]
+    pub iterations: Option<usize>,
+    /// Once we stop, print the share of the completions in the logfile (and
+    /// its per-process logfiles) that each pid has, e.g. after two bounded
+    /// floods at different nice levels have logged to the same place
+    #[arg(long)]
+    pub summary: bool,
+    /// Only run on these CPUs (e.g. `0,2-3`). `--affinity-sweep` then sweeps
+    /// through just these
+    #[arg(long, value_name = "CPUS")]
+    pub cpu_affinity: Option<CpuList>,
 }
 
 #[derive(Args, Clone)]
 pub struct TuiCommand {
     /// The nice level for the first parent process
-    #[arg(long)]
-    pub ni1: NiceLevel,
+    #[arg(long, required_unless_present_any = ["ni", "watch_pid1"])]
+    pub ni1: Option<NiceLevel>,
     /// The nice level for the second parent process
-    #[arg(long)]
-    pub ni2: NiceLevel,
+    #[arg(long, required_unless_present_any = ["ni", "watch_pid"])]
+    pub ni2: Option<NiceLevel>,
+    /// The nice level for both parent processes, for comparing them by
+    /// something else, e.g. their policies. Given more than once, e.g.
+    /// `--ni 0 --ni 10 --ni 19`, this starts one parent process per nice level
+    #[arg(long, conflicts_with_all = ["ni1", "ni2"])]
+    pub ni: Vec<NiceLevel>,
+    /// The scheduling policy for the first parent process
+    #[arg(long, value_enum)]
+    pub policy1: Option<nix::SchedPolicy>,
+    /// The scheduling policy for the second parent process
+    #[arg(long, value_enum)]
+    pub policy2: Option<nix::SchedPolicy>,
+    /// The real-time priority for `--policy1 fifo` or `rr` (see
+    /// `flood --rt-priority`)
+    #[arg(long, value_name = "PRIO", required_if_eq_any = [("policy1", "fifo"), ("policy1", "rr")])]
+    pub rt_priority1: Option<i32>,
+    /// The real-time priority for `--policy2 fifo` or `rr`
+    #[arg(long, value_name = "PRIO", required_if_eq_any = [("policy2", "fifo"), ("policy2", "rr")])]
+    pub rt_priority2: Option<i32>,
+    /// Only run the first parent process on these CPUs (e.g. `0,2-3`). Give
+    /// both processes the same CPU to make them compete directly
+    #[arg(long, value_name = "CPUS")]
+    pub cpu_affinity1: Option<CpuList>,
+    /// Only run the second parent process on these CPUs
+    #[arg(long, value_name = "CPUS")]
+    pub cpu_affinity2: Option<CpuList>,
+    /// Run the first parent process in its own cgroup with this
+    /// `cpu.weight.nice`, to compare it with a plain nice level
+    #[arg(long, value_name = "NI")]
+    pub cgroup_weight_nice1: Option<NiceLevel>,
+    /// Run the second parent process in its own cgroup with this
+    /// `cpu.weight.nice`, to compare it with a plain nice level
+    #[arg(long, value_name = "NI")]
+    pub cgroup_weight_nice2: Option<NiceLevel>,
+    /// Watch this existing process in place of the first parent process,
+    /// instead of spawning it. The existing process is never killed. With
+    /// `--watch-pid` too, this compares two processes that are already running
+    #[arg(
+        long,
+        conflicts_with_all = ["ni1", "policy1", "rt_priority1", "cgroup_weight_nice1", "cpu_affinity1"]
+    )]
+    pub watch_pid1: Option<i32>,
+    /// Compare the first parent process against this existing process, instead
+    /// of spawning a second one. The existing process is never killed.
+    #[arg(
+        long,
+        visible_alias = "watch-pid2",
+        conflicts_with_all = ["ni2", "policy2", "rt_priority2", "cgroup_weight_nice2", "cpu_affinity2"]
+    )]
+    pub watch_pid: Option<i32>,
+    /// The number of threads that each spawned process does its work on (see
+    /// `flood --thread-count`). Press `t` to show each thread's sched instead
+    /// of the process's
+    #[arg(long, short, value_parser = parse_thread_count)]
+    pub thread_count: Option<usize>,
     /// The number of steps in each computation
     #[arg(long, short)]
     pub steps: Option<usize>,
@@ -67,8 +401,275 @@ pub struct TuiCommand {
     #[arg(long, default_value = "/tmp/nicelog")]
     pub logfile: PathBuf,
     /// The path of this program. We need this so that we can start
-    /// subprocesses. By default this is /usr/local/bin/testnice
-    #[arg(long, default_value = "/usr/local/bin/testnice")]
+    /// subprocesses. By default this is the testnice that is running
+    #[arg(long, default_value_os_t = running_testnice())]
+    pub this: PathBuf,
+    /// Pin the tui itself to this CPU, to keep its sampling and rendering off
+    /// the CPUs that the workers are competing for
+    #[arg(long)]
+    pub tui_cpu: Option<usize>,
+    /// Stop the workers and exit after this long (e.g. `10m`), in case the
+    /// session is forgotten about
+    #[arg(long)]
+    pub max_runtime: Option<humantime::Duration>,
+    /// How often to read the sched panes and the log again, in milliseconds.
+    /// Lower is more responsive, but costs more CPU
+    #[arg(long, value_name = "MS", default_value_t = 200, value_parser = parse_refresh_ms)]
+    pub refresh_ms: u64,
+    /// How to line up the values in the sched panes
+    #[arg(long, value_enum, default_value_t)]
+    pub align: Align,
+    /// A copy of a /sched file to compare both processes against, e.g. one
+    /// saved with `cat /proc/[pid]/sched` on a known-good kernel. Values are
+    /// colored green, yellow or red by how far they are from the baseline.
+    #[arg(long)]
+    pub baseline: Option<PathBuf>,
+    /// Have each process log to its own logfile (see `flood --per-process-log`)
+    #[arg(long)]
+    pub per_process_log: bool,
+    /// Carry on when a process isn't allowed its nice level (see `flood
+    /// --best-effort`). Each pane's title shows the nice level its process
+    /// actually has.
+    #[arg(long)]
+    pub best_effort: bool,
+    /// Show `—` for any sched fields that can't be parsed, instead of failing.
+    /// Use this if the tui won't start on your kernel.
+    #[arg(long)]
+    pub tolerant_parse: bool,
+    /// Show sched values exactly as /sched has them, e.g. for diffing
+    /// screenshots. By default, times are given units and the colors have a
+    /// legend
+    #[arg(long)]
+    pub raw: bool,
+    /// Log to a new logfile with the current time appended to `--logfile`
+    /// (e.g. `/tmp/nicelog-20240101-120000`), instead of clearing the old one
+    #[arg(long)]
+    pub unique_log: bool,
+    /// Record every key pressed in the tui to this file, along with when it
+    /// was pressed, so that the session can be replayed with `--replay-keys`
+    #[arg(long, value_name = "PATH")]
+    pub record_keys: Option<PathBuf>,
+    /// Press the keys recorded with `--record-keys` at the same times as they
+    /// were recorded, e.g. for reproducible demos
+    #[arg(long, value_name = "PATH", conflicts_with = "record_keys")]
+    pub replay_keys: Option<PathBuf>,
+    /// Append a row to this tab-separated file every second, with each
+    /// process's completions and the changes in its runtime, vruntime,
+    /// involuntary switches and migrations. This is for plotting long runs
+    #[arg(long, value_name = "PATH")]
+    pub stats_tsv: Option<PathBuf>,
+    /// The directory that `s` (or `j`) saves snapshots to, which is created if it
+    /// doesn't exist
+    #[arg(long, value_name = "DIR", default_value = ".")]
+    pub snapshot_dir: PathBuf,
+    /// The colors to tell the processes and the kinds of field apart with.
+    /// Defaults to $TESTNICE_THEME, or else dark
+    #[arg(long, value_enum)]
+    pub theme: Option<ThemeName>,
+}
+
+#[derive(Args, Clone)]
+pub struct StatsCommand {
+    /// The logfile to summarize. Pass this more than once to merge the counts
+    /// from several logfiles. This defaults to /tmp/nicelog
+    #[arg(long, default_value = "/tmp/nicelog")]
+    pub logfile: Vec<PathBuf>,
+    /// Only count the completions logged in this window before the newest
+    /// completion in each logfile (e.g. `30s`), to leave out the warmup
+    #[arg(long)]
+    pub since: Option<humantime::Duration>,
+    #[arg(long, value_enum, default_value_t)]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Clone)]
+pub struct RunCommand {
+    /// The nice level to run the command at
+    #[arg(long)]
+    pub ni: NiceLevel,
+    /// The command to run, and its arguments
+    #[arg(last = true, required = true)]
+    pub command: Vec<String>,
+}
+
+#[derive(Args, Clone)]
+pub struct SetCommand {
+    /// Renice the processes whose names match this pattern (e.g.
+    /// `^firefox$`). This is matched against /proc/[pid]/comm, which the kernel
+    /// cuts short to 15 characters
+    #[arg(long, value_name = "PATTERN", value_parser = Regex::new)]
+    pub comm_regex: Regex,
+    /// The nice level to set
+    #[arg(long)]
+    pub ni: NiceLevel,
+}
+
+#[derive(Args, Clone)]
+pub struct ReniceCommand {
+    /// Renice every process in this process group, e.g. a shell job
+    #[arg(long, value_name = "PGID", value_parser = clap::value_parser!(i32).range(1..))]
+    pub pgrp: i32,
+    /// The nice level to set
+    #[arg(long)]
+    pub ni: NiceLevel,
+}
+
+#[derive(Args, Clone)]
+pub struct ShowCommand {
+    /// The process to report on
+    #[arg(long)]
+    pub pid: i32,
+}
+
+#[derive(Args, Clone)]
+pub struct WatchCommand {
+    /// The process to watch. It is never signalled, even when we quit
+    #[arg(long)]
+    pub pid: i32,
+    /// How often to read its sched again, in milliseconds
+    #[arg(long, value_name = "MS", default_value_t = 200, value_parser = parse_refresh_ms)]
+    pub refresh_ms: u64,
+    #[arg(long, value_enum, default_value_t)]
+    pub align: Align,
+    /// Show `—` for any sched fields that can't be parsed, instead of failing
+    #[arg(long)]
+    pub tolerant_parse: bool,
+    /// Show sched values exactly as /sched has them (see `tui --raw`)
+    #[arg(long)]
+    pub raw: bool,
+    /// The colors to show the fields in (see `tui --theme`)
+    #[arg(long, value_enum)]
+    pub theme: Option<ThemeName>,
+}
+
+#[derive(Args, Clone)]
+pub struct CompareCommand {
+    /// The nice level for the first process
+    #[arg(long)]
+    pub ni1: NiceLevel,
+    /// The nice level for the second process
+    #[arg(long)]
+    pub ni2: NiceLevel,
+    /// The number of steps in each computation
+    #[arg(long, short)]
+    pub steps: Option<usize>,
+    /// How many times to sample the processes
+    #[arg(long, default_value_t = 5)]
+    pub samples: usize,
+    /// How long to wait before each sample
+    #[arg(long, default_value = "1s")]
+    pub interval: humantime::Duration,
+    /// The path of this program, to start the processes with. By default
+    /// this is the testnice that is running
+    #[arg(long, default_value_os_t = running_testnice())]
+    pub this: PathBuf,
+    /// Show fields that can't be parsed as unknown, instead of failing
+    #[arg(long)]
+    pub tolerant_parse: bool,
+    /// Log the completions of both processes, and print the share that each
+    /// had once they have stopped
+    #[arg(long)]
+    pub summary: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct DumpCommand {
+    /// The process to dump the /sched fields of
+    #[arg(long)]
+    pub pid: i32,
+    #[arg(long, value_enum, default_value_t)]
+    pub format: DumpFormat,
+    /// Print another sample every interval until interrupted, instead of
+    /// printing one and exiting
+    #[arg(long)]
+    pub interval: Option<humantime::Duration>,
+    /// Show fields that can't be parsed as unknown, instead of failing
+    #[arg(long)]
+    pub tolerant_parse: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct WatchdiffCommand {
+    /// The two processes to compare, e.g. `--pid 1234 5678`
+    #[arg(
+        long,
+        required = true,
+        num_args = 2,
+        value_names = ["PID", "PID"],
+        action = clap::ArgAction::Set
+    )]
+    pub pid: Vec<i32>,
+    /// How often to print the differences
+    #[arg(long, default_value = "1s")]
+    pub interval: humantime::Duration,
+    /// Show fields that can't be parsed as unknown, instead of failing
+    #[arg(long)]
+    pub tolerant_parse: bool,
+    #[arg(long, value_enum, default_value_t)]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Clone)]
+pub struct WeightsCommand {
+    /// Print the share of a CPU that each of these two nice levels should get
+    /// when they compete for it, instead of the whole table
+    #[arg(long, num_args = 2, value_names = ["NI1", "NI2"], allow_negative_numbers = true)]
+    pub compare: Option<Vec<NiceLevel>>,
+}
+
+#[derive(Args, Clone)]
+pub struct BenchLogCommand {
+    /// The sizes of the synthetic logfiles to read from, in entries
+    #[arg(long, value_delimiter = ',', default_value = "1000,100000,1000000")]
+    pub entries: Vec<usize>,
+    /// The numbers of entries to read from the end of each logfile
+    #[arg(long, value_delimiter = ',', default_value = "10,100,1000,10000")]
+    pub count: Vec<usize>,
+    /// How many times to time each read
+    #[arg(long, default_value_t = 20)]
+    pub iterations: u32,
+    /// Also log this many random pids, and check that reading any number of
+    /// entries from the end gives back exactly the last pids that were logged
+    #[arg(long, default_value_t = 500, value_name = "ENTRIES")]
+    pub check: usize,
+    /// Also time this many threads logging `--writes` completions each to one
+    /// logfile, with every batch size in `--batch`
+    #[arg(long, default_value_t = 4)]
+    pub threads: usize,
+    #[arg(long, default_value_t = 20000)]
+    pub writes: usize,
+    /// The numbers of completions to write at a time, as `flood --log-batch`
+    #[arg(long, value_delimiter = ',', default_value = "1,16,256")]
+    pub batch: Vec<usize>,
+}
+
+#[derive(Args, Clone)]
+pub struct SweepCommand {
+    /// The first nice level to measure
+    #[arg(long, default_value = "-20", allow_negative_numbers = true)]
+    pub from: NiceLevel,
+    /// The last nice level to measure, if the steps land on it
+    #[arg(long, default_value = "19", allow_negative_numbers = true)]
+    pub to: NiceLevel,
+    /// How far apart the measured nice levels are
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+    pub step: u32,
+    /// The number of steps in each computation
+    #[arg(long, short)]
+    pub steps: Option<usize>,
+    /// The nice level of the process that each level competes against
+    #[arg(long, default_value = "0", allow_negative_numbers = true)]
+    pub reference: NiceLevel,
+    /// How long each level competes for
+    #[arg(long, default_value = "5s")]
+    pub duration: humantime::Duration,
+    /// The CPUs that both processes run on. They only compete for CPU time
+    /// when they share CPUs, so this is a single CPU by default
+    #[arg(long, value_name = "CPUS", default_value = "0")]
+    pub cpu_affinity: CpuList,
+    /// The path of this program, to start the processes with. By default
+    /// this is the testnice that is running
+    #[arg(long, default_value_os_t = running_testnice())]
     pub this: PathBuf,
 }
 
@@ -79,6 +680,42 @@ pub enum Command {
     Flood(FloodCommand),
     /// Open the TUI that allows you to inspect some processes
     Tui(TuiCommand),
+    /// Open a TUI with a single pane that follows the sched of an existing
+    /// process, without spawning anything
+    Watch(WatchCommand),
+    /// Spawn two processes like the tui does, but print a few of their
+    /// /sched fields side by side every interval instead, for when there is
+    /// no terminal
+    Compare(CompareCommand),
+    /// Run a flood at each of a range of nice levels in turn, against one at
+    /// a fixed nice level, and print how many tasks each level completed
+    /// relative to it
+    Sweep(SweepCommand),
+    /// Summarize the completions per process recorded in one or more logfiles
+    Stats(StatsCommand),
+    /// Run any command at the given nice level, like nice(1). The command
+    /// replaces testnice, so its exit code is our exit code
+    Run(RunCommand),
+    /// Renice every thread of every process whose name matches a pattern,
+    /// like `renice` for all of them at once. Kernel threads are skipped
+    Set(SetCommand),
+    /// Renice every thread of every process in a process group at once
+    Renice(ReniceCommand),
+    /// Print everything that decides how much CPU a process gets
+    Show(ShowCommand),
+    /// Print the /sched fields of a process as JSON or CSV, for scripts
+    Dump(DumpCommand),
+    /// Print the /sched fields where two processes differ, every interval,
+    /// until interrupted
+    Watchdiff(WatchdiffCommand),
+    /// Print the weight that CFS gives to each nice level, which decides what
+    /// share of a CPU it gets
+    Weights(WeightsCommand),
+    /// Time reading entries from the end of large synthetic logfiles, and
+    /// check the results against reading the whole file. This is for working
+    /// on the logfile reader.
+    #[command(hide = true)]
+    BenchLog(BenchLogCommand),
 }
 
 #[derive(Parser, Clone)]
@@ -87,3 +724,90 @@ pub struct Cli {
     #[command(subcommand)]
     pub command: Command,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comm_regex(pattern: &str) -> Result<Regex, clap::Error> {
+        let cli = Cli::try_parse_from(["testnice", "set", "--comm-regex", pattern, "--ni", "10"])?;
+        match cli.command {
+            Command::Set(command) => Ok(command.comm_regex),
+            _ => unreachable!("parsed as the set subcommand"),
+        }
+    }
+
+    #[test]
+    fn comm_regex_matches() {
+        let table = [
+            ("^firefox$", "firefox", true),
+            ("^firefox$", "firefox-bin", false),
+            ("fire", "firefox", true),
+            ("^fox", "firefox", false),
+            ("^(firefox|chromium)$", "chromium", true),
+            ("^kworker/[0-9]+", "kworker/3:1", true),
+            ("^kworker/[0-9]+", "kworker/u8:2", false),
+            ("Web Content", "Web Content", true),
+            ("^[^a-z]", "Xorg", true),
+            ("^[^a-z]", "xorg", false),
+            (r"^a\.b$", "a.b", true),
+            (r"^a\.b$", "axb", false),
+        ];
+        for (pattern, comm, expected) in table {
+            let regex = comm_regex(pattern).unwrap();
+            assert_eq!(regex.is_match(comm), expected, "{pattern} against {comm}");
+        }
+    }
+
+    #[test]
+    fn comm_regex_rejects_invalid_patterns() {
+        for pattern in ["(", "[a-", "*firefox", "a{2,1}"] {
+            assert!(comm_regex(pattern).is_err(), "{pattern}");
+        }
+    }
+
+    #[test]
+    fn comm_regex_nested_quantifiers_stay_linear() {
+        // a backtracking matcher takes exponential time to reject this
+        let regex = comm_regex("^(a+)+$").unwrap();
+        assert!(!regex.is_match(&format!("{}!", "a".repeat(64))));
+    }
+
+    #[test]
+    fn target_pid_must_be_positive() {
+        let target_pid = |pid: &str| {
+            let cli = Cli::try_parse_from(["testnice", "flood", "--ni", "0", "--target-pid", pid])?;
+            match cli.command {
+                Command::Flood(command) => Ok(command.target_pid),
+                _ => unreachable!("parsed as the flood subcommand"),
+            }
+        };
+        assert_eq!(target_pid("4321").unwrap(), Some(4321));
+        for pid in ["0", "-5", "pid"] {
+            let e: clap::Error = target_pid(pid).unwrap_err();
+            assert_eq!(e.kind(), clap::error::ErrorKind::ValueValidation, "{pid}");
+        }
+    }
+
+    #[test]
+    fn watchdiff_takes_exactly_two_pids() {
+        let pids = |args: &[&str]| {
+            let cli = Cli::try_parse_from(["testnice", "watchdiff"].iter().chain(args))?;
+            match cli.command {
+                Command::Watchdiff(command) => Ok(command.pid),
+                _ => unreachable!("parsed as the watchdiff subcommand"),
+            }
+        };
+        assert_eq!(pids(&["--pid", "12", "34"]).unwrap(), [12, 34]);
+        for args in [
+            &["--pid", "12"][..],
+            &["--pid", "12", "34", "56"],
+            &["--pid", "12", "--pid", "34"],
+            &["--pid", "12", "34", "--pid", "56", "78"],
+            &[],
+        ] {
+            let e: clap::Error = pids(args).unwrap_err();
+            assert_eq!(e.exit_code(), 2, "{args:?}");
+        }
+    }
+}
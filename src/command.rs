@@ -1,77 +1,752 @@
-use std::path::PathBuf;
-use std::{process, thread};
+use ::nix::libc::{kill, SIGTERM};
+use clap::ValueEnum;
+use std::cmp;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::os::fd::FromRawFd;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
 
-use crate::cli::{FloodCommand, TuiCommand};
-use crate::log::Log;
+use crate::cli::{
+    BenchLogCommand, CompareCommand, Compute, DumpCommand, DumpFormat, DutyCycle, FloodCommand,
+    NiceLevel, OutputFormat, ReniceCommand, RunCommand, SetCommand, ShowCommand, StatsCommand,
+    SweepCommand, TuiCommand, WatchCommand, WatchdiffCommand, WeightsCommand,
+};
+use crate::log::{self, BufferedLog, Log, LogEntry, LogError, LogFormat};
 use crate::nix_ext as nix;
-use crate::tui::Tui;
+use crate::report;
+use crate::theme::ThemeName;
+use crate::tui::{KeySource, ReplayedKeys, StatsTsv, TerminalKeys, Tui, TuiOptions, Watch, Worker};
+use crate::{format_note, format_warn};
 
 /// For all commands we define a common interface for running them
 pub trait Exec: Clone {
-    fn exec(self) -> Result<(), String>;
+    fn exec(self) -> Result<(), AppError>;
 }
 
+/// Why testnice failed. Each kind has its own exit code (following
+/// sysexits.h), so that scripts can tell them apart.
+#[derive(Debug)]
+pub enum AppError {
+    /// We weren't allowed to change a process's scheduling, e.g. a negative
+    /// nice level without CAP_SYS_NICE
+    Permission(String),
+    /// A process that we needed doesn't exist, or we couldn't read its /proc
+    /// files
+    NotFound(String),
+    /// The config file couldn't be read or is invalid
+    Config(String),
+    Other(String),
+}
+
+impl AppError {
+    /// The exit code for this kind of error. Clap exits with 2 for invalid
+    /// arguments, and success is 0.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Other(..) => 1,
+            Self::NotFound(..) => 66,
+            Self::Permission(..) => 77,
+            Self::Config(..) => 78,
+        }
+    }
+
+    /// Put `context` (e.g. the argument that the error is about) in front of
+    /// the message, keeping the kind
+    fn context(self, context: impl fmt::Display) -> Self {
+        match self {
+            Self::Permission(e) => Self::Permission(format!("{context}: {e}")),
+            Self::NotFound(e) => Self::NotFound(format!("{context}: {e}")),
+            Self::Config(e) => Self::Config(format!("{context}: {e}")),
+            Self::Other(e) => Self::Other(format!("{context}: {e}")),
+        }
+    }
+}
+
+impl From<String> for AppError {
+    fn from(e: String) -> Self {
+        Self::Other(e)
+    }
+}
+
+impl From<nix::ReniceError> for AppError {
+    fn from(e: nix::ReniceError) -> Self {
+        match e {
+            nix::ReniceError::Access | nix::ReniceError::Permission => {
+                Self::Permission(e.to_string())
+            }
+            nix::ReniceError::NoSuchProcess(..) | nix::ReniceError::NoSuchProcessGroup(..) => {
+                Self::NotFound(e.to_string())
+            }
+            nix::ReniceError::InvalidNiceLevel(..) => Self::Other(e.to_string()),
+        }
+    }
+}
+
+impl From<nix::GetniceError> for AppError {
+    fn from(e: nix::GetniceError) -> Self {
+        match e {
+            nix::GetniceError::Permission => Self::Permission(e.to_string()),
+            nix::GetniceError::NoSuchTarget => Self::NotFound(e.to_string()),
+            nix::GetniceError::InvalidTarget => Self::Other(e.to_string()),
+        }
+    }
+}
+
+impl From<nix::PolicyError> for AppError {
+    fn from(e: nix::PolicyError) -> Self {
+        match e {
+            nix::PolicyError::Permission => Self::Permission(e.to_string()),
+            nix::PolicyError::NoSuchProcess(..) => Self::NotFound(e.to_string()),
+            nix::PolicyError::Invalid | nix::PolicyError::RtPriority(..) => {
+                Self::Other(e.to_string())
+            }
+        }
+    }
+}
+
+impl From<nix::SchedCreationError> for AppError {
+    fn from(e: nix::SchedCreationError) -> Self {
+        match e {
+            nix::SchedCreationError::FileError => Self::NotFound(e.to_string()),
+            nix::SchedCreationError::GetniceError(e) => e.into(),
+            nix::SchedCreationError::UnexpectedFileFormat => Self::Other(e.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Permission(e) | Self::NotFound(e) | Self::Config(e) | Self::Other(e) => {
+                write!(f, "{e}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+/// The number of steps in each computation if `--steps` is not given
+const DEFAULT_STEPS: usize = 100_000_000;
+
 // #[inline(never)] is just so that this comes up in the assembly in a more
 // clear way. It shouldn't be necessary for this to do what it should do.
 #[inline(never)]
 fn slow_black_box<T>(n: &T, steps: Option<usize>) -> &T {
     let mut acc = n;
-    for _ in 0..steps.unwrap_or(100_000_000) {
+    for _ in 0..steps.unwrap_or(DEFAULT_STEPS) {
         acc = std::hint::black_box(n);
     }
     acc
 }
 
+impl Compute {
+    /// Do `steps` steps of this kernel, starting from `seed`. The result
+    /// depends on every step, so none of them can be optimized out.
+    #[inline(never)]
+    fn run(self, seed: u64, steps: Option<usize>) -> u64 {
+        let steps = steps.unwrap_or(DEFAULT_STEPS);
+        // xorshift64 never reaches 0, but it can't start from there either
+        let mut int = seed | 1;
+        let mut float = seed as f64;
+        let xorshift = |mut x: u64| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^ (x << 17)
+        };
+        match self {
+            Self::Noop => return *slow_black_box(&seed, Some(steps)),
+            Self::Int => {
+                for _ in 0..steps {
+                    int = xorshift(std::hint::black_box(int));
+                }
+            }
+            Self::Float => {
+                for _ in 0..steps {
+                    // this converges on 1e6 instead of overflowing
+                    float = std::hint::black_box(float) * 0.999_999 + 1.0;
+                }
+            }
+            Self::MulAdd => {
+                // written as a multiply and then an add, since `mul_add` is a
+                // slow libm call on CPUs without FMA instructions
+                let (a, b) = std::hint::black_box((0.999_999, 1.0));
+                let mut lanes: [f64; Self::FLOPS_PER_STEP / 2] =
+                    std::array::from_fn(|i| float + i as f64);
+                for _ in 0..steps {
+                    for lane in &mut lanes {
+                        *lane = *lane * a + b;
+                    }
+                }
+                float = lanes.iter().sum();
+            }
+            Self::Mixed => {
+                for _ in 0..steps {
+                    int = xorshift(std::hint::black_box(int));
+                    float = std::hint::black_box(float) * 0.999_999 + (int >> 44) as f64;
+                    int /= (float as u64) | 1;
+                    int |= 1;
+                }
+            }
+        }
+        int ^ float.to_bits()
+    }
+}
+
+/// Moves the calling thread from CPU to CPU, pinning it to a single one at a
+/// time
+struct AffinitySweep {
+    cpus: Vec<usize>,
+    next: usize,
+    interval: Duration,
+    last_change: Instant,
+}
+
+/// Warnings about any of `cpus` that the scheduler treats specially, since
+/// results from them can be misleading
+fn special_cpu_warnings(cpus: &[usize]) -> Vec<String> {
+    nix::special_cpus()
+        .into_iter()
+        .filter(|(cpu, _)| cpus.contains(cpu))
+        .map(|(cpu, reason)| {
+            format_warn!(
+                "cpu {cpu} is in {reason}, so scheduling on it doesn't behave like normal CFS"
+            )
+        })
+        .collect()
+}
+
+impl AffinitySweep {
+    /// Start sweeping through the CPUs that this thread is currently allowed to
+    /// run on, starting by pinning to the first one
+    fn start(interval: Duration) -> Result<Self, String> {
+        let cpus = nix::get_affinity(0).map_err(|e| format!("{e}"))?;
+        let mut sweep = Self {
+            cpus,
+            next: 0,
+            interval,
+            last_change: Instant::now(),
+        };
+        sweep.pin_next()?;
+        Ok(sweep)
+    }
+
+    fn pin_next(&mut self) -> Result<(), String> {
+        let cpu = self.cpus[self.next];
+        self.next = (self.next + 1) % self.cpus.len();
+        nix::set_affinity(0, &[cpu]).map_err(|e| format!("{e}"))
+    }
+
+    /// Pin to the next CPU if we have been on this one for long enough
+    fn tick(&mut self, now: Instant) -> Result<(), String> {
+        if now.duration_since(self.last_change) >= self.interval {
+            self.last_change = now;
+            self.pin_next()?;
+        }
+        Ok(())
+    }
+}
+
+/// Sleeps between computations so that the calling thread uses a target share
+/// of a CPU, by comparing the CPU time it has used against the wall-clock time
+/// over a short window
+struct CpuTarget {
+    target: f64,
+    window_start: Instant,
+    window_start_cpu: u64,
+}
+
+impl CpuTarget {
+    /// How long we measure usage over before starting again. Keeping this
+    /// short means that time spent starved by other processes isn't made up
+    /// for later by not sleeping at all.
+    const WINDOW: Duration = Duration::from_secs(1);
+
+    fn start(target: DutyCycle) -> Self {
+        Self {
+            target: target.fraction(),
+            window_start: Instant::now(),
+            window_start_cpu: nix::thread_cpu_nanos(),
+        }
+    }
+
+    /// Sleep for long enough to bring our usage in this window down to the
+    /// target, if it is above it
+    fn tick(&mut self) {
+        let cpu = Duration::from_nanos(nix::thread_cpu_nanos() - self.window_start_cpu);
+        let wall = self.window_start.elapsed();
+        if let Some(idle) = cpu.div_f64(self.target).checked_sub(wall) {
+            thread::sleep(idle);
+        }
+        if self.window_start.elapsed() >= Self::WINDOW {
+            self.window_start = Instant::now();
+            self.window_start_cpu = nix::thread_cpu_nanos();
+        }
+    }
+}
+
+/// Samples how long our threads wait for a CPU each time they are scheduled,
+/// on a separate thread, until it is stopped
+struct LatencySampler {
+    stop: Arc<AtomicBool>,
+    handle: thread::JoinHandle<Vec<Duration>>,
+}
+
+impl LatencySampler {
+    /// How often we sample. Each sample is the mean wait per timeslice over
+    /// this interval.
+    const INTERVAL: Duration = Duration::from_millis(100);
+
+    fn start() -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                let mut samples = vec![];
+                let Ok(mut last) = nix::SchedStat::of_other_threads() else {
+                    return samples;
+                };
+                while !stop.load(Ordering::Relaxed) && !nix::interrupted() {
+                    thread::sleep(Self::INTERVAL);
+                    let Ok(stat) = nix::SchedStat::of_other_threads() else {
+                        break;
+                    };
+                    // threads that exit take their counts with them
+                    let timeslices = stat.timeslices.saturating_sub(last.timeslices);
+                    let wait_time = stat.wait_time.saturating_sub(last.wait_time);
+                    if let Some(wait) = wait_time.checked_div(timeslices) {
+                        samples.push(Duration::from_nanos(wait));
+                    }
+                    last = stat;
+                }
+                samples
+            }
+        });
+        Self { stop, handle }
+    }
+
+    /// Stop sampling and return the samples, sorted
+    fn finish(self) -> Vec<Duration> {
+        self.stop.store(true, Ordering::Relaxed);
+        let mut samples = self.handle.join().unwrap_or_default();
+        samples.sort();
+        samples
+    }
+}
+
+/// Format a GitHub-flavored Markdown table. The first column is left-aligned
+/// and the rest are right-aligned, since they are normally numbers.
+fn markdown_table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let row = |cells: &mut dyn Iterator<Item = &str>| {
+        let cells = cells
+            .map(|cell| cell.replace('|', "\\|"))
+            .collect::<Vec<_>>();
+        format!("| {} |\n", cells.join(" | "))
+    };
+    let mut table = row(&mut header.iter().copied());
+    let rule = (0..header.len()).map(|i| if i == 0 { ":---" } else { "---:" });
+    table.push_str(&row(&mut rule.into_iter()));
+    for cells in rows {
+        table.push_str(&row(&mut cells.iter().map(String::as_str)));
+    }
+    table
+}
+
+/// The `p`th percentile of `sorted` by the nearest-rank method, or `None` if
+/// it is empty
+fn percentile(sorted: &[Duration], p: f64) -> Option<Duration> {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted.get(rank.saturating_sub(1)).copied()
+}
+
+/// Background floods at nice 19 that compete with the main flood. These are
+/// stopped when this is dropped, however the main flood exits.
+struct Noise {
+    children: Vec<process::Child>,
+}
+
+impl Noise {
+    /// The nice level of the noise processes, which is the lowest priority
+    const NI: i32 = 19;
+
+    /// Spawn `count` noise processes that do the same computations as `flood`
+    fn spawn(flood: &FloodCommand, count: usize) -> Result<Self, String> {
+        let this = env::current_exe().map_err(|e| format!("while finding testnice: {e}"))?;
+        let noise = FloodCommand {
+            steps: flood.steps,
+            compute: flood.compute,
+            ..FloodCommand::plain(NiceLevel::new(Self::NI).expect("19 is a valid nice level"))
+        };
+        // anything already spawned is stopped if a later spawn fails
+        let mut spawned = Self { children: vec![] };
+        for _ in 0..count {
+            let mut command = noise.clone().new_process(&this);
+            command.stdout(process::Stdio::null());
+            let child = command
+                .spawn()
+                .map_err(|_| String::from("while spawning noise processes"))?;
+            spawned.children.push(child);
+        }
+        Ok(spawned)
+    }
+}
+
+impl Drop for Noise {
+    fn drop(&mut self) {
+        stop_children(&mut self.children);
+    }
+}
+
 /// Repeatedly write to the specified logfile the nice level, after completing
-/// a computation with `steps` steps.
-fn loop_and_log(steps: Option<usize>, logfile: Log) -> Result<(), String> {
-    let pid = nix::unistd::Pid::this().as_raw() as i32;
-    loop {
-        let pid = *slow_black_box(&pid, steps);
-        logfile
-            .log_task_completion(pid)
-            .map_err(|e| format!("{e}"))?;
+/// a computation with `flood.steps` steps, until we are interrupted, `stop` is
+/// set, we reach `deadline` or have done the last of the computations counted
+/// down in `remaining`. Each computation is also counted in `completions`.
+/// All of these may be shared between threads.
+fn loop_and_log(
+    flood: &FloodCommand,
+    logfile: Log,
+    completions: &AtomicUsize,
+    remaining: Option<&AtomicUsize>,
+    deadline: Option<Instant>,
+    stop: &AtomicBool,
+) -> Result<(), String> {
+    let pid = nix::unistd::Pid::this().as_raw();
+    // each thread logs its own completions, so that they can be told apart
+    let tid = nix::unistd::gettid().as_raw();
+    let mut sweep = match flood.affinity_sweep {
+        Some(interval) => {
+            let sweep = AffinitySweep::start(interval.into())?;
+            special_cpu_warnings(&sweep.cpus)
+                .into_iter()
+                .for_each(|warning| flood.note(warning));
+            Some(sweep)
+        }
+        None => None,
+    };
+    let mut cpu_target = flood.target_cpu.map(CpuTarget::start);
+    let mut logfile = BufferedLog::new(logfile, flood.log_batch, BufferedLog::MAX_WAIT);
+    while !nix::interrupted() && !stop.load(Ordering::Relaxed) {
+        let burst_start = Instant::now();
+        if deadline.is_some_and(|deadline| burst_start >= deadline) {
+            break;
+        }
+        // claim the computation first, so that the threads do exactly as
+        // many as were asked for between them
+        if let Some(remaining) = remaining {
+            if remaining
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+                .is_err()
+            {
+                break;
+            }
+        }
+        std::hint::black_box(flood.compute.run(pid as u64, flood.steps));
+        if nix::logging_paused() {
+            // what was logged before the pause shouldn't wait until after it
+            logfile.flush().map_err(|e| format!("{e}"))?;
+        } else {
+            logfile
+                .log_task_completion(pid, tid)
+                .map_err(|e| format!("{e}"))?;
+        }
+        completions.fetch_add(1, Ordering::Relaxed);
+        let now = Instant::now();
+        if let Some(sweep) = &mut sweep {
+            sweep.tick(now)?;
+        }
+        if let Some(duty_cycle) = flood.duty_cycle {
+            thread::sleep(duty_cycle.idle_time(now.duration_since(burst_start)));
+        }
+        if let Some(cpu_target) = &mut cpu_target {
+            cpu_target.tick();
+        }
     }
+    logfile.flush().map_err(|e| format!("{e}"))?;
+    Ok(())
 }
 
-/// Duplicate a specific task on a number of threads and return all the results
-fn spawn_many<F, R>(thread_count: usize, f: F) -> Vec<thread::Result<R>>
+/// Duplicate a specific task on a number of threads, and return their handles
+/// so that the caller can decide when to join them
+fn spawn_many<F, R>(thread_count: usize, f: F) -> Vec<JoinHandle<R>>
 where
     F: Fn() -> R + Send + Clone + 'static,
     R: Send + 'static,
 {
-    let mut handles = Vec::with_capacity(thread_count);
-    for _ in 0..thread_count {
-        handles.push(thread::spawn(f.clone()));
+    (0..thread_count)
+        .map(|_| thread::spawn(f.clone()))
+        .collect()
+}
+
+/// A summary of a flood run, for feeding into benchmarking tools
+struct FloodSummary {
+    ni: i32,
+    thread_count: usize,
+    steps: usize,
+    iterations: usize,
+    elapsed: Duration,
+    /// The billions of floating-point operations in each iteration, if we
+    /// know this
+    gflops: Option<f64>,
+}
+
+impl FloodSummary {
+    fn ops_per_sec(&self) -> f64 {
+        let elapsed = self.elapsed.as_secs_f64();
+        if elapsed > 0.0 {
+            self.iterations as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+
+    /// Format this as a single JSON object, in the same spirit as the results
+    /// that hyperfine and criterion export
+    fn to_json(&self) -> String {
+        let json = BenchJson {
+            command: "testnice flood",
+            parameters: BenchParameters {
+                ni: self.ni,
+                thread_count: self.thread_count,
+                steps: self.steps,
+            },
+            iterations: self.iterations,
+            elapsed_secs: self.elapsed.as_secs_f64(),
+            ops_per_sec: self.ops_per_sec(),
+        };
+        serde_json::to_string(&json).expect("a BenchJson has no maps or fallible fields")
+    }
+}
+
+/// What `flood --bench-json` prints, from [`FloodSummary::to_json`]
+#[derive(serde::Serialize)]
+struct BenchJson {
+    command: &'static str,
+    parameters: BenchParameters,
+    iterations: usize,
+    elapsed_secs: f64,
+    ops_per_sec: f64,
+}
+
+/// The arguments that decide how much work a flood does
+#[derive(serde::Serialize)]
+struct BenchParameters {
+    ni: i32,
+    thread_count: usize,
+    steps: usize,
+}
+
+impl fmt::Display for FloodSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} completions in {:.2}s ({:.2}/s",
+            self.iterations,
+            self.elapsed.as_secs_f64(),
+            self.ops_per_sec()
+        )?;
+        if let Some(gflops) = self.gflops {
+            write!(f, ", {:.2} GFLOP/s", gflops * self.ops_per_sec())?;
+        }
+        write!(f, ")")
     }
-    handles.into_iter().map(|handle| handle.join()).collect()
 }
 
 impl Exec for FloodCommand {
-    fn exec(self) -> Result<(), String> {
-        let logfile = Log::create(self.logfile).map_err(|e| format!("{e}"))?;
+    fn exec(mut self) -> Result<(), AppError> {
+        if let Some(gflops) = self.gflops {
+            let steps = gflops * 1e9 / Compute::FLOPS_PER_STEP as f64;
+            self.steps = Some((steps.round() as usize).max(1));
+            self.compute = Compute::MulAdd;
+        }
+        // the tui can pause our logging as soon as it has spawned us
+        nix::catch_pause_toggle();
+        let path = if Log::is_discarded(&self.logfile) {
+            // there is nowhere to put a unique or per-process log either
+            self.note(format_note!(
+                "completions are not logged, since the logfile is /dev/null"
+            ));
+            self.logfile.clone()
+        } else if self.unique_log {
+            let path = Log::unique_path(&self.logfile);
+            self.note(format_note!("logging to {}", path.display()));
+            path
+        } else {
+            self.logfile.clone()
+        };
+        let (delim, format) = if self.json_lines {
+            (b'\n', LogFormat::JsonLines)
+        } else {
+            (Log::ENTRY_DELIM, LogFormat::Compact)
+        };
+        let logfile = if self.per_process_log && !Log::is_discarded(&path) {
+            Log::create_shard(
+                &path,
+                nix::unistd::Pid::this().as_raw(),
+                Log::MAX_FILE_SIZE,
+                delim,
+                format,
+            )
+        } else {
+            Log::create(path.clone(), Log::MAX_FILE_SIZE, delim, format)
+        }
+        .map_err(|e| format!("{e}"))?;
+        if let Some(cpus) = &self.cpu_affinity {
+            let online = nix::online_cpu_list().unwrap_or_default();
+            if let Some(cpu) = cpus.get().iter().find(|cpu| !online.contains(cpu)) {
+                return Err(format!("--cpu-affinity: cpu {cpu} is not online").into());
+            }
+            special_cpu_warnings(cpus.get())
+                .into_iter()
+                .for_each(|warning| self.note(warning));
+        }
+        let online_cpus = nix::online_cpus();
+        let threads = self.threads();
+        if threads > online_cpus {
+            self.note(format_note!(
+                "{threads} threads on {online_cpus} online CPUs ({:.1}x oversubscribed), so our \
+                own threads will compete with each other and be switched out involuntarily",
+                threads as f64 / online_cpus as f64
+            ));
+        }
+        let _noise = Noise::spawn(&self, self.noise)?;
+        // before we spawn any threads, so that they are all in the cgroup
+        let _cgroup = self
+            .cgroup_weight_nice
+            .map(|ni| nix::TransientCgroup::join_with_weight_nice(ni.get()))
+            .transpose()
+            .map_err(|e| format!("{e}"))?;
+        let start = Instant::now();
 
-        nix::renice(self.ni.get()).map_err(|e| format!("{e}"))?;
-        if self.thread_count > 1 {
-            let results = spawn_many(self.thread_count, move || {
-                loop_and_log(self.steps, logfile.clone())
+        self.ni = self.renice()?;
+        if let Some(pid) = self.target_pid {
+            nix::renice_pid(pid, self.ni.get())
+                .map_err(|e| AppError::from(e).context(format!("--target-pid {pid}")))?;
+        }
+        if let Some(policy) = self.policy {
+            nix::set_policy(0, policy, self.rt_priority.unwrap_or(0))?;
+        }
+        if self.reset_on_fork {
+            nix::set_reset_on_fork(0)?;
+        }
+        // this is inherited by the threads we spawn, like the timer slack
+        if let Some(cpus) = &self.cpu_affinity {
+            nix::set_affinity(0, cpus.get()).map_err(|e| format!("--cpu-affinity: {e}"))?;
+        }
+        // this is per-thread, so it has to be set before we spawn any
+        if let Some(ns) = self.timer_slack {
+            nix::set_timer_slack(ns).map_err(|e| format!("while setting timer slack: {e}"))?;
+        }
+        // stop after the computation we are in the middle of, so that we can
+        // still say how much we got done
+        nix::catch_interrupt();
+        if let Some(fd) = self.report_fd {
+            // SAFETY: the fd was opened for us by the tui, and nothing else
+            // uses it
+            let file = unsafe { File::from_raw_fd(fd) };
+            thread::spawn(move || report::send_reports(file));
+        }
+        let latency = LatencySampler::start();
+        let completions = Arc::new(AtomicUsize::new(0));
+        let remaining = self.iterations.map(|n| Arc::new(AtomicUsize::new(n)));
+        let deadline = self.duration.map(|duration| start + *duration);
+        // set when any thread fails, so that the rest stop too
+        let stop = Arc::new(AtomicBool::new(false));
+        if threads > 1 {
+            let flood = self.clone();
+            let completions = completions.clone();
+            let remaining = remaining.clone();
+            let stop = stop.clone();
+            let handles = spawn_many(threads, move || {
+                let result = loop_and_log(
+                    &flood,
+                    logfile.clone(),
+                    &completions,
+                    remaining.as_deref(),
+                    deadline,
+                    &stop,
+                );
+                if result.is_err() {
+                    stop.store(true, Ordering::Relaxed);
+                }
+                result
             });
 
-            for result in results {
-                match result {
-                    Ok(result) => match result {
-                        Err(e) => return Err(e),
-                        _ => {}
-                    },
+            // join every thread before returning, even once one has failed,
+            // so that none are left running
+            let mut first_error = None;
+            for handle in handles {
+                let e = match handle.join() {
+                    Ok(Ok(())) => continue,
+                    Ok(Err(e)) => e,
                     // The thread panicked somewhere. This should never happen
                     // under normal use.
-                    Err(..) => return Err(String::from("please submit a bug report")),
-                }
+                    Err(..) => String::from("please submit a bug report"),
+                };
+                first_error.get_or_insert(e);
+            }
+            if let Some(e) = first_error {
+                return Err(e.into());
             }
         } else {
-            // we need to do this because otherwise /sched is not updated 
+            // we need to do this because otherwise /sched is not updated
             // properly
-            loop_and_log(self.steps, logfile.clone())?;
+            loop_and_log(
+                &self,
+                logfile.clone(),
+                &completions,
+                remaining.as_deref(),
+                deadline,
+                &stop,
+            )?;
+        }
+
+        let summary = FloodSummary {
+            ni: self.ni.get(),
+            thread_count: threads,
+            steps: self.steps.unwrap_or(DEFAULT_STEPS),
+            iterations: completions.load(Ordering::Relaxed),
+            elapsed: start.elapsed(),
+            gflops: self.gflops,
+        };
+        let latency = latency.finish();
+        // with both --iterations and --duration, whichever came first
+        let finished_iterations = remaining.is_some_and(|n| n.load(Ordering::Relaxed) == 0);
+        let stopped_by = match (self.iterations, self.duration) {
+            _ if nix::interrupted() => Some(String::from("interrupted")),
+            (Some(iterations), _) if finished_iterations => {
+                Some(format!("finished {iterations} iterations"))
+            }
+            (_, Some(duration)) => Some(format!("finished after {duration}")),
+            (Some(..), None) => unreachable!("we only stop early when interrupted"),
+            (None, None) => None,
+        };
+        if let Some(stopped_by) = stopped_by {
+            self.note(format_note!("{stopped_by}, {summary}"));
+            if let [Some(p50), Some(p90), Some(p99)] =
+                [50.0, 90.0, 99.0].map(|p| percentile(&latency, p))
+            {
+                self.note(format_note!(
+                    "scheduling latency p50 {p50:.2?}, p90 {p90:.2?}, p99 {p99:.2?} ({} samples \
+                    of the mean wait per timeslice)",
+                    latency.len()
+                ));
+            }
+        }
+        if self.bench_json {
+            println!("{}", summary.to_json());
+        }
+        if self.summary {
+            self.note(summary_line(&path)?);
         }
 
         Ok(())
@@ -79,55 +754,1275 @@ impl Exec for FloodCommand {
 }
 
 impl FloodCommand {
+    /// A flood at `ni` with every other argument left at its default, like
+    /// `testnice flood --ni=<ni>`
+    fn plain(ni: NiceLevel) -> Self {
+        Self {
+            ni,
+            best_effort: false,
+            thread_count: None,
+            steps: None,
+            compute: Compute::default(),
+            gflops: None,
+            logfile: PathBuf::from("/dev/null"),
+            affinity_sweep: None,
+            duty_cycle: None,
+            target_cpu: None,
+            timer_slack: None,
+            reset_on_fork: false,
+            bench_json: false,
+            per_process_log: false,
+            json_lines: false,
+            log_batch: 1,
+            policy: None,
+            rt_priority: None,
+            unique_log: false,
+            report_fd: None,
+            noise: 0,
+            cgroup_weight_nice: None,
+            target_pid: None,
+            duration: None,
+            iterations: None,
+            summary: false,
+            cpu_affinity: None,
+        }
+    }
+
+    /// Print a note or a warning. With `--bench-json`, stdout is only for the
+    /// JSON summary, so these go to stderr instead.
+    fn note(&self, line: String) {
+        match self.bench_json {
+            true => eprintln!("{line}"),
+            false => println!("{line}"),
+        }
+    }
+
+    /// The number of threads doing the work, counting the main thread if it
+    /// does it
+    pub fn threads(&self) -> usize {
+        self.thread_count.unwrap_or(1)
+    }
+
+    /// Set our nice level to `--ni`, and return the nice level we end up at.
+    /// With `--best-effort`, not being allowed to is only a warning.
+    fn renice(&self) -> Result<NiceLevel, AppError> {
+        match nix::renice(self.ni.get()) {
+            Ok(()) => Ok(self.ni),
+            Err(nix::ReniceError::Access | nix::ReniceError::Permission) if self.best_effort => {
+                let actual = nix::getnice(nix::unistd::Pid::this().as_raw())?;
+                self.note(format_warn!(
+                    "not allowed to set nice level {}, so carrying on at {actual}",
+                    self.ni.get()
+                ));
+                Ok(NiceLevel::new(actual).expect("the kernel only has valid nice levels"))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
     /// Convert this [`FloodCommand`] into a [`std::process::Command`]
     /// representing it
     fn new_process(self, testnice: &PathBuf) -> process::Command {
         let mut command = process::Command::new(testnice);
         command.arg("flood");
         command.arg(format!("--ni={}", self.ni.get()));
-        command.arg(format!("--thread-count={}", self.thread_count));
+        if self.best_effort {
+            command.arg("--best-effort");
+        }
+        if let Some(thread_count) = self.thread_count {
+            command.arg(format!("--thread-count={thread_count}"));
+        }
         if let Some(steps) = self.steps {
             command.arg(format!("--steps={}", steps));
         }
+        let compute = self
+            .compute
+            .to_possible_value()
+            .expect("no kernels are skipped");
+        command.arg(format!("--compute={}", compute.get_name()));
         command.arg(format!("--logfile={}", self.logfile.display()));
+        if let Some(interval) = self.affinity_sweep {
+            command.arg(format!("--affinity-sweep={interval}"));
+        }
+        if let Some(duty_cycle) = self.duty_cycle {
+            command.arg(format!("--duty-cycle={duty_cycle}"));
+        }
+        if let Some(target_cpu) = self.target_cpu {
+            command.arg(format!("--target-cpu={target_cpu}"));
+        }
+        if let Some(ns) = self.timer_slack {
+            command.arg(format!("--timer-slack={ns}"));
+        }
+        if self.reset_on_fork {
+            command.arg("--reset-on-fork");
+        }
+        if self.bench_json {
+            command.arg("--bench-json");
+        }
+        if self.per_process_log {
+            command.arg("--per-process-log");
+        }
+        if self.json_lines {
+            command.arg("--json-lines");
+        }
+        if self.log_batch != 1 {
+            command.arg(format!("--log-batch={}", self.log_batch));
+        }
+        if let Some(policy) = self.policy {
+            let policy = policy
+                .to_possible_value()
+                .expect("only settable policies are used");
+            command.arg(format!("--policy={}", policy.get_name()));
+        }
+        if let Some(prio) = self.rt_priority {
+            command.arg(format!("--rt-priority={prio}"));
+        }
+        if let Some(fd) = self.report_fd {
+            command.arg(format!("--report-fd={fd}"));
+        }
+        if self.noise > 0 {
+            command.arg(format!("--noise={}", self.noise));
+        }
+        if let Some(ni) = self.cgroup_weight_nice {
+            command.arg(format!("--cgroup-weight-nice={}", ni.get()));
+        }
+        if let Some(pid) = self.target_pid {
+            command.arg(format!("--target-pid={pid}"));
+        }
+        if let Some(iterations) = self.iterations {
+            command.arg(format!("--iterations={iterations}"));
+        }
+        if let Some(duration) = self.duration {
+            command.arg(format!("--duration={duration}"));
+        }
+        if let Some(cpus) = &self.cpu_affinity {
+            command.arg(format!("--cpu-affinity={cpus}"));
+        }
         command
     }
 
-    fn spawn_process(self, testnice: &PathBuf) -> Result<process::Child, String> {
+    /// Spawn this as a child process of the tui. Its stdout is discarded,
+    /// since the summary it prints when stopped would be drawn over the tui.
+    pub fn spawn_process(self, testnice: &PathBuf) -> Result<process::Child, String> {
         let mut command = self.new_process(testnice);
         command
+            .stdout(process::Stdio::null())
             .spawn()
-            .map_err(|_| String::from("while spawning child processes"))
+            .map_err(|e| format!("while spawning {}: {e}", testnice.display()))
+    }
+}
+
+/// Check that `this` is an executable file before we spawn floods with it, so
+/// that a wrong `--this` gets a better error than the spawn would
+fn check_this(this: &Path) -> Result<(), AppError> {
+    let problem = match fs::metadata(this) {
+        Ok(metadata) if !metadata.is_file() => String::from("not a file"),
+        Ok(metadata) if metadata.permissions().mode() & 0o111 == 0 => {
+            String::from("not executable")
+        }
+        Ok(..) => return Ok(()),
+        Err(e) => e.to_string(),
+    };
+    let hint = match env::current_exe() {
+        Ok(exe) if exe != this => format!(
+            " (leave out --this to use the testnice that is running, {})",
+            exe.display()
+        ),
+        _ => String::new(),
+    };
+    Err(AppError::NotFound(format!(
+        "--this {}: {problem}{hint}",
+        this.display()
+    )))
+}
+
+/// PREEMPT_RT kernels leave out some /sched fields, so we have to be tolerant
+/// of them there even if we weren't asked to be
+fn tolerate_preempt_rt(tolerant_parse: &mut bool) {
+    if !*tolerant_parse && nix::is_preempt_rt() {
+        println!(
+            "{}",
+            format_note!(
+                "this is a PREEMPT_RT kernel, so /sched fields that are missing or can't be \
+                parsed are shown as unknown"
+            )
+        );
+        *tolerant_parse = true;
     }
 }
 
 impl Exec for TuiCommand {
-    fn exec(self) -> Result<(), String> {
+    fn exec(mut self) -> Result<(), AppError> {
+        // better to fail here than on the first sample
+        nix::check_procfs().map_err(|e| format!("{e}"))?;
+        // or once the floods are running
+        Tui::check_terminal().map_err(|e| e.to_string())?;
+        check_this(&self.this)?;
+        let theme = ThemeName::or_from_env(self.theme).map_err(AppError::Config)?;
+        tolerate_preempt_rt(&mut self.tolerant_parse);
+        if let Some(cpu) = self.tui_cpu {
+            special_cpu_warnings(&[cpu])
+                .into_iter()
+                .for_each(|warning| println!("{warning}"));
+        }
+        let governors = nix::cpu_governors();
+        if governors.iter().any(|governor| governor != "performance") {
+            println!(
+                "{}",
+                format_warn!(
+                    "the cpufreq governor is {}, so clock speeds can change under load and skew \
+                    throughput comparisons. To avoid this, run \
+                    `sudo cpupower frequency-set -g performance`",
+                    governors.join("/")
+                )
+            );
+        }
+        let baseline = match &self.baseline {
+            Some(path) => Some(
+                nix::Sched::load(path, self.tolerant_parse)
+                    .map_err(|e| format!("baseline {}: {e}", path.display()))?,
+            ),
+            None => None,
+        };
+        // flood is fine with discarding its completions, but we show them
+        if Log::is_discarded(&self.logfile) {
+            let e = LogError::NotRegularFile(self.logfile.clone());
+            return Err(format!("--logfile: {e}").into());
+        }
+        if let Some(pid) = self.watch_pid1 {
+            nix::Sched::read_raw(pid)
+                .map_err(|e| AppError::from(e).context(format!("--watch-pid1 {pid}")))?;
+        }
+        if let Some(pid) = self.watch_pid {
+            nix::Sched::read_raw(pid)
+                .map_err(|e| AppError::from(e).context(format!("--watch-pid {pid}")))?;
+        }
+        let keys: Box<dyn KeySource> = match (&self.record_keys, &self.replay_keys) {
+            (_, Some(path)) => Box::new(
+                ReplayedKeys::load(path)
+                    .map_err(|e| format!("--replay-keys {}: {e}", path.display()))?,
+            ),
+            (Some(path), None) => Box::new(
+                TerminalKeys::recording_to(path)
+                    .map_err(|e| format!("--record-keys {}: {e}", path.display()))?,
+            ),
+            (None, None) => Box::new(TerminalKeys::new()),
+        };
+        // a single --ni is shared by both processes, and each extra one adds
+        // another process
+        let count = self.ni.len().max(2);
+        let stats_tsv = match &self.stats_tsv {
+            Some(path) => Some(
+                StatsTsv::open(path.clone(), count)
+                    .map_err(|e| format!("--stats-tsv {}: {e}", path.display()))?,
+            ),
+            None => None,
+        };
+
+        // the workers have to be told which logfile we chose
+        let path = if self.unique_log {
+            Log::unique_path(&self.logfile)
+        } else {
+            self.logfile.clone()
+        };
+        let logfile = Log::create(
+            path,
+            Log::MAX_FILE_SIZE,
+            Log::ENTRY_DELIM,
+            LogFormat::Compact,
+        )
+        .map_err(|e| format!("{e}"))?;
+
         // Using fork() here introduces too much added complexity and I just
         // can't be bothered + don't think it's worth it.
-        let child1 = FloodCommand {
-            ni: self.ni1,
-            thread_count: 1,
+        let mut workers = Vec::with_capacity(count);
+        for i in 0..count {
+            // only the first two processes have their own options
+            let (watch_pid, ni, policy, rt_priority, cgroup_weight_nice, cpu_affinity) = match i {
+                0 => (
+                    self.watch_pid1,
+                    self.ni1,
+                    self.policy1,
+                    self.rt_priority1,
+                    self.cgroup_weight_nice1,
+                    self.cpu_affinity1.clone(),
+                ),
+                1 => (
+                    self.watch_pid,
+                    self.ni2,
+                    self.policy2,
+                    self.rt_priority2,
+                    self.cgroup_weight_nice2,
+                    self.cpu_affinity2.clone(),
+                ),
+                _ => (None, None, None, None, None, None),
+            };
+            let worker = match watch_pid {
+                Some(pid) => Ok(Worker::watch(pid)),
+                None => Worker::spawn(
+                    FloodCommand {
+                        best_effort: self.best_effort,
+                        thread_count: self.thread_count,
+                        steps: self.steps,
+                        logfile: logfile.path().to_path_buf(),
+                        per_process_log: self.per_process_log,
+                        policy,
+                        rt_priority,
+                        cgroup_weight_nice,
+                        cpu_affinity,
+                        ..FloodCommand::plain(
+                            ni.or_else(|| self.ni.get(i).or(self.ni.first()).copied())
+                                .expect("clap requires --ni1/--ni2 or --ni"),
+                        )
+                    },
+                    &self.this,
+                ),
+            };
+            match worker {
+                Ok(worker) => workers.push(worker),
+                Err(e) => {
+                    // don't leave the ones we already started running
+                    for worker in &mut workers {
+                        worker.stop();
+                    }
+                    return Err(e.into());
+                }
+            }
+        }
+
+        let path = logfile.path().to_path_buf();
+        Tui::start(
+            workers,
+            logfile,
+            TuiOptions {
+                this: self.this,
+                tui_cpu: self.tui_cpu,
+                max_runtime: self.max_runtime.map(Into::into),
+                refresh: Duration::from_millis(self.refresh_ms),
+                align: self.align,
+                baseline,
+                tolerant_parse: self.tolerant_parse,
+                raw_values: self.raw,
+                keys,
+                stats_tsv,
+                snapshot_dir: self.snapshot_dir,
+                theme: theme.theme(),
+            },
+        )
+        .map_err(|e| e.to_string())?;
+
+        if self.unique_log {
+            println!("{}", format_note!("logged to {}", path.display()));
+        }
+        Ok(())
+    }
+}
+
+/// Print the completions for each pid, and the share of all completions that
+/// this represents
+fn print_counts(title: &str, counts: &HashMap<i32, usize>, format: OutputFormat) {
+    match format {
+        OutputFormat::Text => println!("{title}"),
+        OutputFormat::Markdown => println!("**{title}**\n"),
+    }
+    let total: usize = counts.values().sum();
+    if total == 0 {
+        match format {
+            OutputFormat::Text => println!("  no completions"),
+            OutputFormat::Markdown => println!("_no completions_\n"),
+        }
+        return;
+    }
+
+    let mut counts = counts.iter().collect::<Vec<_>>();
+    counts.sort();
+    let mut rows = vec![];
+    for (pid, count) in counts {
+        let share = *count as f64 / total as f64 * 100.0;
+        match format {
+            OutputFormat::Text => println!("  {pid:>8} {count:>10} {share:>6.2}%"),
+            OutputFormat::Markdown => rows.push(vec![
+                pid.to_string(),
+                count.to_string(),
+                format!("{share:.2}%"),
+            ]),
+        }
+    }
+    match format {
+        OutputFormat::Text => println!("  {:>8} {total:>10}", "total"),
+        OutputFormat::Markdown => {
+            rows.push(vec![
+                String::from("total"),
+                total.to_string(),
+                String::new(),
+            ]);
+            println!(
+                "{}",
+                markdown_table(&["pid", "completions", "share"], &rows)
+            );
+        }
+    }
+}
+
+impl Exec for StatsCommand {
+    fn exec(self) -> Result<(), AppError> {
+        let mut merged = HashMap::new();
+        // which file each pid was first seen in, so we can warn about pids
+        // that were reused between runs
+        let mut seen_in: HashMap<i32, &PathBuf> = HashMap::new();
+
+        for path in &self.logfile {
+            let counts = Log::load_all(path.clone())
+                .and_then(|logs| Log::count_entries_merged(&logs, self.since.map(Into::into)))
+                .map_err(|e| format!("{}: {e}", path.display()))?;
+
+            print_counts(&path.display().to_string(), &counts, self.format);
+
+            for (&pid, &count) in &counts {
+                match seen_in.get(&pid) {
+                    Some(other) => println!(
+                        "{}",
+                        format_warn!(
+                            "pid {pid} appears in both {} and {}, so its counts will be merged",
+                            other.display(),
+                            path.display()
+                        )
+                    ),
+                    None => _ = seen_in.insert(pid, path),
+                }
+                *merged.entry(pid).or_insert(0) += count;
+            }
+        }
+
+        if self.logfile.len() > 1 {
+            let title = format!("merged ({} logfiles)", self.logfile.len());
+            print_counts(&title, &merged, self.format);
+        }
+
+        Ok(())
+    }
+}
+
+impl Exec for RunCommand {
+    fn exec(self) -> Result<(), AppError> {
+        nix::renice(self.ni.get())?;
+
+        // the nice level is inherited across exec, and the command takes over
+        // this process (and so its exit code) from here
+        let (program, args) = self
+            .command
+            .split_first()
+            .expect("clap requires at least one argument");
+        let e = process::Command::new(program).args(args).exec();
+        Err(format!("while running {program}: {e}").into())
+    }
+}
+
+impl Exec for SetCommand {
+    fn exec(self) -> Result<(), AppError> {
+        let ni = self.ni.get();
+        let processes =
+            nix::user_processes().map_err(|e| format!("while listing processes: {e}"))?;
+        let mut reniced = 0;
+        let mut failed = vec![];
+        // we could match ourselves, but nobody wants that
+        let this = process::id() as i32;
+        for process in processes
+            .iter()
+            .filter(|process| process.pid != this && self.comm_regex.is_match(&process.comm))
+        {
+            // the process may have exited since we listed it, and so may any
+            // of its threads
+            let Ok(tids) = nix::thread_ids(process.pid) else {
+                continue;
+            };
+            let result = tids
+                .into_iter()
+                .try_for_each(|tid| match nix::renice_pid(tid, ni) {
+                    Err(nix::ReniceError::NoSuchProcess(..)) => Ok(()),
+                    result => result,
+                });
+            match result {
+                Ok(()) => {
+                    println!("{:>8} {}", process.pid, process.comm);
+                    reniced += 1;
+                }
+                Err(e) => failed.push((process, e)),
+            }
+        }
+
+        if let Some((process, e)) = failed.first() {
+            println!(
+                "{}",
+                format_warn!(
+                    "could not renice {} matching processes, e.g. {} ({}): {e}",
+                    failed.len(),
+                    process.pid,
+                    process.comm
+                )
+            );
+        }
+        match (reniced, failed.len()) {
+            (0, 0) => println!("{}", format_note!("no processes matched")),
+            // normally because we aren't allowed to, which scripts care about
+            (0, _) => {
+                let e = String::from("could not renice any matching processes");
+                return Err(match failed.first() {
+                    Some((_, nix::ReniceError::Access | nix::ReniceError::Permission)) => {
+                        AppError::Permission(e)
+                    }
+                    _ => AppError::Other(e),
+                });
+            }
+            (reniced, _) => println!("{}", format_note!("reniced {reniced} processes to {ni}")),
+        }
+        Ok(())
+    }
+}
+
+impl Exec for ReniceCommand {
+    fn exec(self) -> Result<(), AppError> {
+        let (pgid, ni) = (self.pgrp, self.ni.get());
+        // this also checks that the group has any processes in it
+        let context = |e| AppError::context(e, format!("process group {pgid}"));
+        let before = nix::getnice_pgid(pgid).map_err(|e| context(e.into()))?;
+        nix::renice_pgid(pgid, ni).map_err(|e| context(e.into()))?;
+        println!(
+            "{}",
+            format_note!(
+                "reniced process group {pgid} to {ni} (its lowest nice level was {before})"
+            )
+        );
+        Ok(())
+    }
+}
+
+impl Exec for ShowCommand {
+    fn exec(self) -> Result<(), AppError> {
+        /// Show a value, or why we couldn't get it
+        fn or_reason<T: fmt::Display, E: fmt::Display>(result: Result<T, E>) -> String {
+            match result {
+                Ok(val) => format!("{val}"),
+                Err(e) => format!("unavailable ({e})"),
+            }
+        }
+
+        // this also checks that the process exists, which getnice can't
+        let pid = self.pid;
+        let policy = nix::get_policy(pid)?;
+
+        let mut report = vec![
+            ("nice", or_reason(nix::getnice(pid))),
+            ("policy", format!("{policy}")),
+            ("reset_on_fork", or_reason(nix::reset_on_fork(pid))),
+        ];
+        if policy.is_realtime() {
+            report.push(("rt_priority", or_reason(nix::rt_priority(pid))));
+        }
+        let affinity = nix::get_affinity(pid).map(|cpus| {
+            let cpus = cpus.iter().map(|cpu| cpu.to_string()).collect::<Vec<_>>();
+            cpus.join(",")
+        });
+        report.push(("affinity", or_reason(affinity)));
+        match nix::cgroup_cpu_limits(pid) {
+            Ok(limits) if limits.is_empty() => report.push(("cgroup", String::from("no limits"))),
+            Ok(limits) => {
+                for (file_name, contents) in limits {
+                    report.push((file_name, contents));
+                }
+            }
+            Err(e) => report.push(("cgroup", format!("unavailable ({e})"))),
+        }
+        report.push(("autogroup nice", or_reason(nix::autogroup_nice(pid))));
+        report.push(("oom_score_adj", or_reason(nix::oom_score_adj(pid))));
+        report.push(("timer slack (ns)", or_reason(nix::timer_slack(pid))));
+
+        println!("pid {pid}");
+        for (key, val) in report {
+            println!("  {key:<18} {val}");
+        }
+        Ok(())
+    }
+}
+
+/// Reads one field of a [`nix::Sched`] as a number
+type SchedField = fn(&nix::Sched) -> f64;
+
+impl CompareCommand {
+    /// The fields that we compare, as `(field_name, value, precision)`. The
+    /// times are in ms, and the rest are counts.
+    const FIELDS: [(&'static str, SchedField, usize); 3] = [
+        ("se.vruntime", |sched| sched.vruntime, 3),
+        ("se.sum_exec_runtime", |sched| sched.sum_exec_runtime, 3),
+        (
+            "nr_involuntary_switches",
+            |sched| sched.nr_involuntary_switches as f64,
+            0,
+        ),
+    ];
+
+    /// Print a row for every field, for sample number `sample`
+    fn print_sample(sample: usize, a: &nix::Sched, b: &nix::Sched) {
+        for (field_name, field, precision) in Self::FIELDS {
+            let (a, b) = (field(a), field(b));
+            println!(
+                "{sample:>6}  {field_name:<24} {a:>16.precision$} {b:>16.precision$} \
+                {:>+16.precision$}",
+                b - a
+            );
+        }
+    }
+
+    /// Sample the processes `pids` every interval, until we have enough
+    /// samples or are interrupted
+    fn sample(&self, pids: [i32; 2]) -> Result<(), String> {
+        let [ni1, ni2] = [self.ni1.get(), self.ni2.get()];
+        println!(
+            "{:>6}  {:<24} {:>16} {:>16} {:>16}",
+            "sample",
+            "field",
+            format!("ni {ni1}"),
+            format!("ni {ni2}"),
+            "delta"
+        );
+        for sample in 1..=self.samples {
+            thread::sleep(self.interval.into());
+            if nix::interrupted() {
+                break;
+            }
+            let [a, b] = pids.map(|pid| {
+                nix::Sched::of_with(pid, self.tolerant_parse).map_err(|e| format!("pid {pid}: {e}"))
+            });
+            Self::print_sample(sample, &a?, &b?);
+        }
+        Ok(())
+    }
+}
+
+impl Exec for WatchCommand {
+    fn exec(mut self) -> Result<(), AppError> {
+        // better to fail here than on the first sample
+        nix::check_procfs().map_err(|e| format!("{e}"))?;
+        let theme = ThemeName::or_from_env(self.theme).map_err(AppError::Config)?;
+        tolerate_preempt_rt(&mut self.tolerant_parse);
+        nix::Sched::read_raw(self.pid)
+            .map_err(|e| AppError::from(e).context(format!("--pid {}", self.pid)))?;
+        Watch::start(
+            self.pid,
+            Duration::from_millis(self.refresh_ms),
+            self.align,
+            self.tolerant_parse,
+            self.raw,
+            theme.theme(),
+        )
+        .map_err(|e| AppError::Other(e.to_string()))
+    }
+}
+
+impl Exec for CompareCommand {
+    fn exec(mut self) -> Result<(), AppError> {
+        nix::check_procfs().map_err(|e| format!("{e}"))?;
+        check_this(&self.this)?;
+        tolerate_preempt_rt(&mut self.tolerant_parse);
+        // each process logs to its own file, so that neither clears what the
+        // other has logged when it starts
+        let logfile = match self.summary {
+            true => env::temp_dir().join(format!("testnice-compare-{}", process::id())),
+            false => PathBuf::from("/dev/null"),
+        };
+        let flood = |ni| FloodCommand {
             steps: self.steps,
-            logfile: self.logfile.clone(),
+            logfile: logfile.clone(),
+            per_process_log: self.summary,
+            ..FloodCommand::plain(ni)
+        };
+
+        let mut children = vec![];
+        for ni in [self.ni1, self.ni2] {
+            match flood(ni).spawn_process(&self.this) {
+                Ok(child) => children.push(child),
+                Err(e) => {
+                    stop_children(&mut children);
+                    return Err(e.into());
+                }
+            }
+        }
+        println!(
+            "{}",
+            format_note!(
+                "comparing pid {} (ni {}) with pid {} (ni {})",
+                children[0].id(),
+                self.ni1.get(),
+                children[1].id(),
+                self.ni2.get()
+            )
+        );
+
+        // stop sampling early on ^C, but still stop the children
+        nix::catch_interrupt();
+        let result = self.sample([children[0].id() as i32, children[1].id() as i32]);
+        // the children log their last completions as they stop
+        stop_children(&mut children);
+        if self.summary {
+            let summary = summary_line(&logfile).map(|line| println!("{line}"));
+            if let Ok(logs) = Log::load_all(logfile) {
+                logs.iter().for_each(|log| _ = log.remove());
+            }
+            summary?;
+        }
+        Ok(result?)
+    }
+}
+
+/// A note with the share of the completions that each pid has in the logfile
+/// at `path` and its per-process logfiles, biggest first, e.g. `pid 123
+/// completed 73.0% of tasks (730), pid 456 27.0% (270)`
+fn summary_line(path: &Path) -> Result<String, String> {
+    if Log::is_discarded(path) {
+        return Ok(format_warn!(
+            "there is no summary, since completions are not logged to /dev/null"
+        ));
+    }
+    let counts = Log::load_all(path.to_path_buf())
+        .and_then(|logs| Log::count_entries_merged(&logs, None))
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+    let total: usize = counts.values().sum();
+    if total == 0 {
+        return Ok(format_note!(
+            "no completions were logged, so there is no summary"
+        ));
+    }
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by_key(|&(pid, count)| (cmp::Reverse(count), pid));
+    let shares = counts
+        .iter()
+        .enumerate()
+        .map(|(i, (pid, count))| {
+            let share = *count as f64 / total as f64 * 100.0;
+            match i {
+                0 => format!("pid {pid} completed {share:.1}% of tasks ({count})"),
+                _ => format!("pid {pid} {share:.1}% ({count})"),
+            }
+        })
+        .collect::<Vec<_>>();
+    Ok(format_note!("{}", shares.join(", ")))
+}
+
+impl Exec for SweepCommand {
+    fn exec(self) -> Result<(), AppError> {
+        check_this(&self.this)?;
+        let (from, to) = (self.from.get(), self.to.get());
+        let levels = match from <= to {
+            true => (from..=to).step_by(self.step as usize).collect::<Vec<_>>(),
+            false => (to..=from).rev().step_by(self.step as usize).collect(),
+        };
+        let logfile = env::temp_dir().join(format!("testnice-sweep-{}", process::id()));
+        println!(
+            "{}",
+            format_note!(
+                "each level competes with nice {} for {} on CPUs {}, so this takes about {}",
+                self.reference.get(),
+                self.duration,
+                self.cpu_affinity,
+                humantime::format_duration(*self.duration * levels.len() as u32)
+            )
+        );
+
+        // ^C stops the floods too, since they are in our process group, and
+        // then we stop before the next level
+        nix::catch_interrupt();
+        println!(
+            "{:>6} {:>12} {:>12} {:>10} {:>10}",
+            "nice", "completions", "reference", "relative", "expected"
+        );
+        for ni in levels {
+            let ni = NiceLevel::new(ni).expect("between two valid nice levels");
+            let counts = self.run_level(ni, &logfile);
+            if let Ok(logs) = Log::load_all(logfile.clone()) {
+                logs.iter().for_each(|log| _ = log.remove());
+            }
+            let (completions, reference) = counts?;
+            if nix::interrupted() {
+                println!(
+                    "{}",
+                    format_note!("interrupted during nice {}, so it is left out", ni.get())
+                );
+                break;
+            }
+            let relative = match reference {
+                0 => String::from("-"),
+                _ => format!("{:.3}x", completions as f64 / reference as f64),
+            };
+            let expected = ni.weight() as f64 / self.reference.weight() as f64;
+            let expected = format!("{expected:.3}x");
+            println!(
+                "{:>6} {completions:>12} {reference:>12} {relative:>10} {expected:>10}",
+                ni.get()
+            );
         }
-        .spawn_process(&self.this)?;
+        Ok(())
+    }
+}
 
-        let child2 = FloodCommand {
-            ni: self.ni2,
-            thread_count: 1,
+impl SweepCommand {
+    /// Run a flood at `ni` against one at the reference nice level until
+    /// both have stopped, and return how many tasks each completed
+    fn run_level(&self, ni: NiceLevel, logfile: &Path) -> Result<(usize, usize), AppError> {
+        let flood = |ni| FloodCommand {
             steps: self.steps,
-            logfile: self.logfile.clone(),
+            // each process logs to its own file, so that neither clears what
+            // the other has logged when it starts
+            logfile: logfile.to_path_buf(),
+            per_process_log: true,
+            duration: Some(self.duration),
+            cpu_affinity: Some(self.cpu_affinity.clone()),
+            ..FloodCommand::plain(ni)
+        };
+        let mut children = vec![];
+        for ni in [ni, self.reference] {
+            match flood(ni).spawn_process(&self.this) {
+                Ok(child) => children.push(child),
+                Err(e) => {
+                    stop_children(&mut children);
+                    return Err(e.into());
+                }
+            }
+        }
+        for (i, ni) in [ni, self.reference].into_iter().enumerate() {
+            let status = children[i].wait();
+            let e = match status {
+                Ok(status) if status.success() => continue,
+                // its output is discarded, but its exit code says what kind
+                // of problem it was
+                Ok(status) => {
+                    let e = format!("the flood at nice {} failed ({status})", ni.get());
+                    match status.code() {
+                        Some(77) => AppError::Permission(e),
+                        _ => AppError::Other(e),
+                    }
+                }
+                Err(e) => AppError::Other(format!("while waiting for a flood: {e}")),
+            };
+            stop_children(&mut children[i + 1..]);
+            return Err(e);
         }
-        .spawn_process(&self.this)?;
+        let counts = Log::load_all(logfile.to_path_buf())
+            .and_then(|logs| Log::count_entries_merged(&logs, None))
+            .map_err(|e| format!("{}: {e}", logfile.display()))?;
+        let [completions, reference] = [&children[0], &children[1]]
+            .map(|child| counts.get(&(child.id() as i32)).copied().unwrap_or(0));
+        Ok((completions, reference))
+    }
+}
 
-        Tui::start(
-            child1.id() as _,
-            child2.id() as _,
-            Log::create(self.logfile).map_err(|e| format!("{e}"))?,
-        )
-        .map_err(|e| e.to_string())?;
+/// Terminate child processes and wait for them to exit
+fn stop_children(children: &mut [process::Child]) {
+    for child in children {
+        _ = unsafe { kill(child.id() as _, SIGTERM) };
+        _ = child.wait();
+    }
+}
+
+impl Exec for DumpCommand {
+    fn exec(mut self) -> Result<(), AppError> {
+        tolerate_preempt_rt(&mut self.tolerant_parse);
+        let pid = self.pid;
+        let sample = || {
+            nix::Sched::of_with(pid, self.tolerant_parse)
+                .map_err(|e| AppError::from(e).context(format!("pid {pid}")))
+        };
 
+        nix::catch_interrupt();
+        let mut first = true;
+        while !nix::interrupted() {
+            let sched = sample()?;
+            match self.format {
+                DumpFormat::Json => println!("{}", sched.to_json()),
+                DumpFormat::Csv => {
+                    if first {
+                        println!("{}", sched.csv_header());
+                    }
+                    println!("{}", sched.to_csv());
+                }
+            }
+            first = false;
+            match self.interval {
+                Some(interval) => thread::sleep(interval.into()),
+                None => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Exec for WatchdiffCommand {
+    fn exec(mut self) -> Result<(), AppError> {
+        tolerate_preempt_rt(&mut self.tolerant_parse);
+        let (pid_a, pid_b) = (self.pid[0], self.pid[1]);
+        let sample = |pid| {
+            nix::Sched::of_with(pid, self.tolerant_parse)
+                .map_err(|e| AppError::from(e).context(format!("pid {pid}")))
+        };
+
+        nix::catch_interrupt();
+        while !nix::interrupted() {
+            let (a, b) = (sample(pid_a)?, sample(pid_b)?);
+            // one block per sample, so that the output can be appended to a
+            // file and grepped by timestamp or field
+            let now = humantime::format_rfc3339_seconds(SystemTime::now());
+            match self.format {
+                OutputFormat::Text => println!("--- {now} {pid_a} vs {pid_b}"),
+                OutputFormat::Markdown => println!("#### {now}\n"),
+            }
+            let mut rows = vec![];
+            for field in a.diff(&b).changed() {
+                let (field_name, val_a, val_b) = (field.field_name, &field.from, &field.to);
+                let delta = field.delta.to_string();
+                match self.format {
+                    OutputFormat::Text => {
+                        println!("{field_name:<28} {val_a:>20} {val_b:>20} {delta:>20}")
+                    }
+                    OutputFormat::Markdown => rows.push(vec![
+                        String::from(field_name),
+                        val_a.clone(),
+                        val_b.clone(),
+                        delta,
+                    ]),
+                }
+            }
+            if self.format == OutputFormat::Markdown {
+                let (pid_a, pid_b) = (pid_a.to_string(), pid_b.to_string());
+                let header = ["field", &pid_a, &pid_b, "delta"];
+                println!("{}", markdown_table(&header, &rows));
+            }
+            thread::sleep(self.interval.into());
+        }
+        Ok(())
+    }
+}
+
+impl Exec for WeightsCommand {
+    fn exec(self) -> Result<(), AppError> {
+        match self.compare.as_deref() {
+            Some([ni1, ni2]) => {
+                let (weight1, weight2) = (ni1.weight() as f64, ni2.weight() as f64);
+                let total = weight1 + weight2;
+                println!("{:>6} {:>8} {:>8}", "nice", "weight", "share");
+                for (ni, weight) in [(ni1, weight1), (ni2, weight2)] {
+                    let share = 100.0 * weight / total;
+                    println!("{:>6} {weight:>8} {share:>7.2}%", ni.get());
+                }
+                println!(
+                    "nice {} should get {:.2}x as much CPU time as nice {}, when both \
+                    are runnable on the same CPU",
+                    ni1.get(),
+                    weight1 / weight2,
+                    ni2.get()
+                );
+            }
+            Some(_) => unreachable!("clap takes exactly two values for --compare"),
+            None => {
+                // relative to nice 0, so that each step of ~1.25x is visible
+                println!("{:>6} {:>8} {:>10}", "nice", "weight", "vs nice 0");
+                for ni in -20..=19 {
+                    let weight = nix::nice_weight(ni);
+                    let relative = weight as f64 / nix::nice_weight(0) as f64;
+                    println!("{ni:>6} {weight:>8} {relative:>9.3}x");
+                }
+            }
+        }
         Ok(())
     }
 }
+
+impl Exec for BenchLogCommand {
+    fn exec(self) -> Result<(), AppError> {
+        let path = env::temp_dir().join(format!("testnice-bench-{}", process::id()));
+        let result = self
+            .bench(&path)
+            .and_then(|_| self.bench_writes(&path))
+            .and_then(|_| {
+                // the default delimiter, one that isn't, and json lines
+                [
+                    (Log::ENTRY_DELIM, LogFormat::Compact),
+                    (b'\n', LogFormat::Compact),
+                    (b'\n', LogFormat::JsonLines),
+                ]
+                .into_iter()
+                .try_for_each(|(delim, format)| self.check(&path, delim, format))
+            });
+        _ = fs::remove_file(&path);
+        Ok(result?)
+    }
+}
+
+impl BenchLogCommand {
+    /// Time `self.threads` threads logging `self.writes` completions each to
+    /// one new logfile, a batch at a time for every batch size, and check
+    /// that every completion was written. The threads wait on each other for
+    /// the lock, so the time per lock taken is about how long a write holds
+    /// it.
+    fn bench_writes(&self, path: &Path) -> Result<(), String> {
+        if self.threads == 0 || self.writes == 0 {
+            return Ok(());
+        }
+        println!(
+            "{:>10} {:>10} {:>14} {:>14} {:>14}",
+            "threads", "batch", "total", "per lock", "per entry"
+        );
+        let pid = nix::unistd::Pid::this().as_raw();
+        for &batch in &self.batch {
+            _ = fs::remove_file(path);
+            let log = Log::create(
+                path.to_path_buf(),
+                u64::MAX,
+                Log::ENTRY_DELIM,
+                LogFormat::Compact,
+            )
+            .map_err(|e| format!("{e}"))?;
+            let writes = self.writes;
+            let start = Instant::now();
+            let handles = (0..self.threads)
+                .map(|_| {
+                    let log = log.clone();
+                    thread::spawn(move || {
+                        let tid = nix::unistd::gettid().as_raw();
+                        // only full batches are written until the end, so
+                        // that we know how many locks were taken
+                        let mut log = BufferedLog::new(log, batch, Duration::MAX);
+                        for _ in 0..writes {
+                            log.log_task_completion(pid, tid)?;
+                        }
+                        // the rest is written on drop, like when a flood is
+                        // interrupted
+                        Ok::<_, LogError>(tid)
+                    })
+                })
+                .collect::<Vec<_>>();
+            let mut tids = Vec::with_capacity(handles.len());
+            for handle in handles {
+                let tid = handle
+                    .join()
+                    .map_err(|_| String::from("a writer panicked"))?
+                    .map_err(|e| format!("{e}"))?;
+                tids.push(tid);
+            }
+            let total = start.elapsed();
+
+            let (entries, _) = log.read_entries_from(0).map_err(|e| format!("{e}"))?;
+            let counts = log::count_by_tid(&entries);
+            if let Some(tid) = tids
+                .iter()
+                .find(|tid| counts.get(tid).copied().unwrap_or(0) != writes)
+            {
+                return Err(format!(
+                    "with batches of {batch}, thread {tid} logged {writes} completions but {} \
+                    were written",
+                    counts.get(tid).copied().unwrap_or(0)
+                ));
+            }
+            let locks = self.threads * writes.div_ceil(batch.max(1));
+            let per_lock = format!("{:.2?}", total / locks as u32);
+            let per_entry = format!("{:.2?}", total / (self.threads * writes) as u32);
+            let total = format!("{total:.2?}");
+            println!(
+                "{:>10} {batch:>10} {total:>14} {per_lock:>14} {per_entry:>14}",
+                self.threads
+            );
+        }
+        Ok(())
+    }
+
+    fn bench(&self, path: &Path) -> Result<(), String> {
+        println!("{:>10} {:>10} {:>14}  result", "entries", "count", "mean");
+        let mut mismatches = 0;
+        for &entries in &self.entries {
+            // a few interleaved pids with increasing timestamps, like a real run
+            let mut contents = String::new();
+            for i in 0..entries {
+                let pid = 1000 + i % 3;
+                let timestamp = 1_000_000_000_000 + i * 1000;
+                contents.push_str(&format!(
+                    "{pid}{}{timestamp}{}",
+                    LogEntry::TIMESTAMP_SEP,
+                    Log::ENTRY_DELIM as char
+                ));
+            }
+            fs::write(path, contents).map_err(|e| format!("while writing logfile: {e}"))?;
+            let log = Log::load(path.to_path_buf()).map_err(|e| format!("{e}"))?;
+
+            // the naive way: read everything and keep the end
+            let (all, _) = log.read_entries_from(0).map_err(|e| format!("{e}"))?;
+            for &count in &self.count {
+                let expected = &all[all.len().saturating_sub(count)..];
+                let actual = log.read_entries(count).map_err(|e| format!("{e}"))?;
+                let result = if actual.iter().eq(expected.iter()) {
+                    "ok"
+                } else {
+                    mismatches += 1;
+                    "MISMATCH"
+                };
+
+                let start = Instant::now();
+                for _ in 0..self.iterations {
+                    std::hint::black_box(log.read_entries(count).map_err(|e| format!("{e}"))?);
+                }
+                let mean = start.elapsed() / self.iterations.max(1);
+                let mean = format!("{mean:.2?}");
+                println!("{entries:>10} {count:>10} {mean:>14}  {result}");
+            }
+        }
+
+        if mismatches > 0 {
+            return Err(format!(
+                "read_entries disagreed with reading the whole file {mismatches} times"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Log `self.check` random pids to a logfile in `format` delimited by
+    /// `delim`, then read back every number of entries from 0 to one more
+    /// than that, and the whole file. The pids have between 1 and 7 digits, so that entries
+    /// straddle chunk boundaries at every offset.
+    fn check(&self, path: &Path, delim: u8, format: LogFormat) -> Result<(), String> {
+        if self.check == 0 {
+            return Ok(());
+        }
+        let check_empty = |log: &Log, what| {
+            let entries = log.read_entries(self.check).map_err(|e| format!("{e}"))?;
+            let counts = log.tally().map_err(|e| format!("{e}"))?;
+            if !entries.is_empty() || !counts.is_empty() {
+                return Err(format!(
+                    "with {format:?} entries and delimiter {:?}, read_entries or tally found \
+                    entries in {what}",
+                    delim as char
+                ));
+            }
+            Ok(())
+        };
+        // an empty file from before logfiles had headers, then a new logfile
+        // in the same place
+        fs::write(path, "").map_err(|e| format!("while writing logfile: {e}"))?;
+        let empty = Log::load(path.to_path_buf()).map_err(|e| format!("{e}"))?;
+        check_empty(&empty, "an empty file")?;
+        let log = Log::create(path.to_path_buf(), Log::MAX_FILE_SIZE, delim, format)
+            .map_err(|e| format!("{e}"))?;
+        check_empty(&log, "a new logfile")?;
+        let mut x = nix::monotonic_nanos() | 1;
+        let mut pids = Vec::with_capacity(self.check);
+        for _ in 0..self.check {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            // a pid of 0 isn't valid in a logfile
+            let pid = (x % 10u64.pow(1 + (x >> 32) as u32 % 7)).max(1) as i32;
+            // about half of the entries are from a thread other than the main
+            // one, so have a tid too
+            let tid = pid + (x >> 63) as i32;
+            log.log_task_completion(pid, tid)
+                .map_err(|e| format!("{e}"))?;
+            pids.push((pid, tid));
+        }
+
+        let mut mismatches = Vec::new();
+        for count in 0..=pids.len() + 1 {
+            let entries = log.read_entries(count).map_err(|e| format!("{e}"))?;
+            let expected = &pids[pids.len().saturating_sub(count)..];
+            if !entries
+                .iter()
+                .map(|entry| (entry.pid, entry.tid))
+                .eq(expected.iter().copied())
+            {
+                mismatches.push(count);
+            }
+        }
+        let (all, _) = log.read_entries_from(0).map_err(|e| format!("{e}"))?;
+        if !all
+            .iter()
+            .map(|entry| (entry.pid, entry.tid))
+            .eq(pids.iter().copied())
+        {
+            mismatches.push(pids.len());
+        }
+        // windows that start part way back, including past the start
+        let mut window_mismatches = Vec::new();
+        for skip in [1, 37, pids.len() / 2, pids.len(), pids.len() + 1] {
+            for count in [1, 10, pids.len()] {
+                let entries = log
+                    .read_entries_window(skip, count)
+                    .map_err(|e| format!("{e}"))?;
+                let end = pids.len().saturating_sub(skip);
+                let expected = &pids[end.saturating_sub(count)..end];
+                if !entries
+                    .iter()
+                    .map(|entry| (entry.pid, entry.tid))
+                    .eq(expected.iter().copied())
+                {
+                    window_mismatches.push((skip, count));
+                }
+            }
+        }
+        if !window_mismatches.is_empty() {
+            return Err(format!(
+                "with {format:?} entries and delimiter {:?}, read_entries_window(skip, count) \
+                didn't give the count pids logged before the last skip, for (skip, count) in \
+                {window_mismatches:?}",
+                delim as char
+            ));
+        }
+        let mut expected_counts = HashMap::new();
+        for &(pid, _) in &pids {
+            *expected_counts.entry(pid).or_insert(0) += 1;
+        }
+        if log.tally().map_err(|e| format!("{e}"))? != expected_counts {
+            return Err(format!(
+                "with {format:?} entries and delimiter {:?}, tally didn't count the pids logged",
+                delim as char
+            ));
+        }
+        let delim = delim as char;
+        if !mismatches.is_empty() {
+            return Err(format!(
+                "with {format:?} entries and delimiter {delim:?}, read_entries(count) didn't give \
+                the last count pids logged, for count in {mismatches:?}"
+            ));
+        }
+        println!(
+            "with {format:?} entries and delimiter {delim:?}, read_entries gave the last pids \
+            logged for every count up to {} and in windows further back, and tally counted them",
+            pids.len()
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spawn_many_workers_all_complete() {
+        for thread_count in [0, 1, 8, 64] {
+            let completions = Arc::new(AtomicUsize::new(0));
+            let handles = spawn_many(thread_count, {
+                let completions = completions.clone();
+                move || {
+                    thread::sleep(Duration::from_millis(10));
+                    completions.fetch_add(1, Ordering::Relaxed);
+                    thread::current().id()
+                }
+            });
+            assert_eq!(handles.len(), thread_count);
+            let mut ids = handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(completions.load(Ordering::Relaxed), thread_count);
+            // each on a thread of its own
+            ids.sort_by_key(|id| format!("{id:?}"));
+            ids.dedup();
+            assert_eq!(ids.len(), thread_count);
+        }
+    }
+}
@@ -0,0 +1,387 @@
+use clap::ValueEnum;
+use std::{
+    env,
+    error::Error,
+    fmt, fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+use toml::Spanned;
+
+use crate::cli::{Compute, NiceLevel};
+use crate::tui::Align;
+
+/// The only version of the config file that we understand
+const SCHEMA_VERSION: i64 = 1;
+
+/// The arguments that are only required when a field's argument isn't given,
+/// as `(field_name, arguments)`. Clap doesn't count a default as given, so
+/// these stop being required when the field is in the config.
+const REQUIRED_UNLESS: [(&str, &[&str]); 1] = [("ni", &["ni1", "ni2"])];
+
+/// The subcommands that the config sets defaults for. These only spawn our own
+/// floods, so a value from the config can't change some other process that the
+/// user forgot to give it for, like `renice` without `--ni` would.
+const SUBCOMMANDS: [&str; 4] = ["flood", "tui", "compare", "sweep"];
+
+fn parse_value_enum<T: ValueEnum>(val: &str) -> Result<T, String> {
+    T::from_str(val, false).map_err(|_| {
+        let names = T::value_variants()
+            .iter()
+            .filter_map(|variant| Some(String::from(variant.to_possible_value()?.get_name())))
+            .collect::<Vec<_>>();
+        format!("expected one of {}", names.join(", "))
+    })
+}
+
+/// The line that `span` starts on, counting from 1
+fn line_of(contents: &str, span: Range<usize>) -> usize {
+    contents[..span.start].matches('\n').count() + 1
+}
+
+#[derive(Debug)]
+pub enum ConfigErrorKind {
+    /// We couldn't read the file, even though it exists or was asked for
+    Io(io::Error),
+    /// The file isn't valid TOML, or has a field that we don't know or a
+    /// value of the wrong type, as toml describes it
+    Malformed(String),
+    InvalidValue {
+        field: &'static str,
+        reason: String,
+    },
+    MissingSchemaVersion,
+    UnsupportedSchemaVersion(i64),
+}
+
+/// A problem with the config file, and the line it is on if there is one
+#[derive(Debug)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub kind: ConfigErrorKind,
+}
+
+impl fmt::Display for ConfigErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "could not read config file ({e})"),
+            Self::Malformed(message) => write!(f, "{message}"),
+            Self::InvalidValue { field, reason } => write!(f, "invalid `{field}`: {reason}"),
+            Self::MissingSchemaVersion => write!(
+                f,
+                "missing `schema_version` (this version of testnice expects {SCHEMA_VERSION})"
+            ),
+            Self::UnsupportedSchemaVersion(version) => write!(
+                f,
+                "unsupported `schema_version` {version} (this version of testnice expects {SCHEMA_VERSION})"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{line}: {}", self.path.display(), self.kind),
+            None => write!(f, "{}: {}", self.path.display(), self.kind),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            ConfigErrorKind::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// The config file as it is written. Every field is the default for the
+/// argument of the same name, for every subcommand that has it. The values
+/// are kept with where they are in the file, so that a problem with one can
+/// be reported on its line.
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct ConfigFile {
+    #[serde(rename = "schema_version")]
+    schema_version: Option<Spanned<i64>>,
+    ni: Option<Spanned<i32>>,
+    steps: Option<Spanned<usize>>,
+    logfile: Option<Spanned<String>>,
+    compute: Option<Spanned<String>>,
+    align: Option<Spanned<String>>,
+    tui_cpu: Option<Spanned<usize>>,
+    tolerant_parse: Option<Spanned<bool>>,
+}
+
+/// Defaults for command line arguments, read from a TOML file. For example
+///
+/// ```toml
+/// schema_version = 1
+/// logfile = "/var/tmp/nicelog"
+/// tolerant-parse = true
+/// ```
+#[derive(Debug, Default)]
+pub struct Config {
+    /// The value of each field that is set, as it would be given on the
+    /// command line
+    values: Vec<(&'static str, String)>,
+}
+
+impl Config {
+    /// The path of the config file, which is `$TESTNICE_CONFIG` if that is set.
+    /// Otherwise, this is `testnice/config.toml` in the user's config
+    /// directory, if they have one.
+    fn path() -> Option<(PathBuf, bool)> {
+        if let Some(path) = env::var_os("TESTNICE_CONFIG") {
+            return Some((PathBuf::from(path), true));
+        }
+        let config_dir = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some((config_dir.join("testnice/config.toml"), false))
+    }
+
+    /// Load the config file. It is fine for there to be no config file, unless
+    /// `$TESTNICE_CONFIG` asks for one.
+    pub fn load() -> Result<Self, ConfigError> {
+        let Some((path, required)) = Self::path() else {
+            return Ok(Self::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                Self::parse(&contents).map_err(|(line, kind)| ConfigError { path, line, kind })
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => Ok(Self::default()),
+            Err(e) => Err(ConfigError {
+                path,
+                line: None,
+                kind: ConfigErrorKind::Io(e),
+            }),
+        }
+    }
+
+    /// Parse and validate the contents of a config file. Errors come with the
+    /// line they are on, counting from 1, if they are on one.
+    fn parse(contents: &str) -> Result<Self, (Option<usize>, ConfigErrorKind)> {
+        let file: ConfigFile = toml::from_str(contents).map_err(|e| {
+            let line = e.span().map(|span| line_of(contents, span));
+            (line, ConfigErrorKind::Malformed(String::from(e.message())))
+        })?;
+
+        let mut config = Self::default();
+        config.push(contents, "ni", &file.ni, |&ni| match NiceLevel::new(ni) {
+            Some(..) => Ok(ni.to_string()),
+            None => Err(String::from("invalid nice level")),
+        })?;
+        config.push(
+            contents,
+            "steps",
+            &file.steps,
+            |steps| Ok(steps.to_string()),
+        )?;
+        config.push(
+            contents,
+            "logfile",
+            &file.logfile,
+            |logfile| match logfile.is_empty() {
+                true => Err(String::from("must not be empty")),
+                false => Ok(logfile.clone()),
+            },
+        )?;
+        config.push(contents, "compute", &file.compute, |compute| {
+            parse_value_enum::<Compute>(compute).map(|_| compute.clone())
+        })?;
+        config.push(contents, "align", &file.align, |align| {
+            parse_value_enum::<Align>(align).map(|_| align.clone())
+        })?;
+        config.push(
+            contents,
+            "tui-cpu",
+            &file.tui_cpu,
+            |cpu| Ok(cpu.to_string()),
+        )?;
+        config.push(
+            contents,
+            "tolerant-parse",
+            &file.tolerant_parse,
+            |tolerant| Ok(tolerant.to_string()),
+        )?;
+
+        match &file.schema_version {
+            Some(version) if *version.get_ref() != SCHEMA_VERSION => {
+                let kind = ConfigErrorKind::UnsupportedSchemaVersion(*version.get_ref());
+                Err((Some(line_of(contents, version.span())), kind))
+            }
+            None if !config.values.is_empty() => Err((None, ConfigErrorKind::MissingSchemaVersion)),
+            _ => Ok(config),
+        }
+    }
+
+    /// Validate the value of a field, if it is set, and keep it as it would be
+    /// given on the command line
+    fn push<T>(
+        &mut self,
+        contents: &str,
+        field: &'static str,
+        val: &Option<Spanned<T>>,
+        validate: impl FnOnce(&T) -> Result<String, String>,
+    ) -> Result<(), (Option<usize>, ConfigErrorKind)> {
+        if let Some(val) = val {
+            let val_str = validate(val.get_ref()).map_err(|reason| {
+                let line = line_of(contents, val.span());
+                (Some(line), ConfigErrorKind::InvalidValue { field, reason })
+            })?;
+            self.values.push((field, val_str));
+        }
+        Ok(())
+    }
+
+    /// Use the values in this config as the defaults for the arguments of the
+    /// same name in each of [`SUBCOMMANDS`] of `command`
+    pub fn apply(&self, mut command: clap::Command) -> clap::Command {
+        for subcommand in SUBCOMMANDS {
+            command = command.mut_subcommand(subcommand, |mut subcommand| {
+                for (field, val) in &self.values {
+                    let id = field.replace('-', "_");
+                    if subcommand
+                        .get_arguments()
+                        .any(|arg| arg.get_id() == id.as_str())
+                    {
+                        // a required argument can't be missing once it has a
+                        // default
+                        subcommand = subcommand
+                            .mut_arg(id, |arg| arg.default_value(val.clone()).required(false));
+                    }
+                    for (_, required) in REQUIRED_UNLESS.iter().filter(|(name, _)| name == field) {
+                        for &id in *required {
+                            if subcommand.get_arguments().any(|arg| arg.get_id() == id) {
+                                subcommand = subcommand.mut_arg(id, |arg| {
+                                    arg.required_unless_present(
+                                        clap::builder::Resettable::<clap::Id>::Reset,
+                                    )
+                                });
+                            }
+                        }
+                    }
+                }
+                subcommand
+            });
+        }
+        command
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(contents: &str) -> Vec<(&'static str, String)> {
+        match Config::parse(contents) {
+            Ok(config) => config.values,
+            Err((line, kind)) => panic!("{line:?}: {kind}"),
+        }
+    }
+
+    fn error(contents: &str) -> (Option<usize>, ConfigErrorKind) {
+        Config::parse(contents).expect_err("config should be rejected")
+    }
+
+    #[test]
+    fn config_accepts_any_toml_syntax() {
+        let contents = concat!(
+            "schema_version = 1 # the first version\n",
+            "logfile = 'C:\\tmp\\nicelog'\n",
+            "compute = \"\\u0069nt\"\n",
+            "\n",
+            "tolerant-parse = true\n",
+        );
+        assert_eq!(
+            values(contents),
+            [
+                ("logfile", String::from("C:\\tmp\\nicelog")),
+                ("compute", String::from("int")),
+                ("tolerant-parse", String::from("true")),
+            ]
+        );
+        let escaped = values("schema_version = 1\nlogfile = \"/tmp/\\\"quoted\\\"\"\n");
+        assert_eq!(escaped, [("logfile", String::from("/tmp/\"quoted\""))]);
+    }
+
+    #[test]
+    fn config_empty_file_needs_no_schema_version() {
+        assert!(values("# nothing here\n").is_empty());
+    }
+
+    #[test]
+    fn config_rejects_unknown_fields_and_tables() {
+        for contents in [
+            "schema_version = 1\n\nnice = 3\n",
+            "schema_version = 1\n\n[ni]\nx = 1\n",
+        ] {
+            match error(contents) {
+                (Some(3), ConfigErrorKind::Malformed(..)) => {}
+                (line, kind) => panic!("{contents:?}: {line:?}: {kind}"),
+            }
+        }
+    }
+
+    #[test]
+    fn config_reports_invalid_values_on_their_line() {
+        match error("schema_version = 1\nsteps = 10\nni = 40\n") {
+            (Some(3), ConfigErrorKind::InvalidValue { field: "ni", .. }) => {}
+            (line, kind) => panic!("{line:?}: {kind}"),
+        }
+        match error("schema_version = 1\ncompute = \"quantum\"\n") {
+            (
+                Some(2),
+                ConfigErrorKind::InvalidValue {
+                    field: "compute", ..
+                },
+            ) => {}
+            (line, kind) => panic!("{line:?}: {kind}"),
+        }
+        match error("schema_version = 1\nsteps = -1\n") {
+            (Some(2), ConfigErrorKind::Malformed(..)) => {}
+            (line, kind) => panic!("{line:?}: {kind}"),
+        }
+    }
+
+    #[test]
+    fn config_checks_schema_version() {
+        match error("ni = 0\n") {
+            (None, ConfigErrorKind::MissingSchemaVersion) => {}
+            (line, kind) => panic!("{line:?}: {kind}"),
+        }
+        match error("ni = 0\nschema_version = 2\n") {
+            (Some(2), ConfigErrorKind::UnsupportedSchemaVersion(2)) => {}
+            (line, kind) => panic!("{line:?}: {kind}"),
+        }
+    }
+
+    #[test]
+    fn config_only_sets_defaults_for_our_floods() {
+        let config = Config::parse("schema_version = 1\nni = 7\n").unwrap();
+        let command = config.apply(<crate::cli::Cli as clap::CommandFactory>::command());
+        let renice = command
+            .clone()
+            .try_get_matches_from(["testnice", "renice", "--pgrp", "4321"]);
+        assert_eq!(
+            renice.unwrap_err().kind(),
+            clap::error::ErrorKind::MissingRequiredArgument
+        );
+        let set =
+            command
+                .clone()
+                .try_get_matches_from(["testnice", "set", "--comm-regex", "firefox"]);
+        assert!(set.is_err());
+        let flood = command.try_get_matches_from(["testnice", "flood"]).unwrap();
+        let (_, flood) = flood.subcommand().unwrap();
+        assert_eq!(
+            flood.get_one::<NiceLevel>("ni").map(NiceLevel::get),
+            Some(7)
+        );
+    }
+}
@@ -0,0 +1,29 @@
+//! Tools for testing how nice levels and scheduling policies affect CPU
+//! time, as used by the `testnice` binary.
+//!
+//! The most useful parts are re-exported here:
+//!
+//! - [`Sched`] reads a process's `/proc/<pid>/sched`, and [`SchedPolicy`]
+//!   names its scheduling policy
+//! - [`renice`], [`renice_pid`] and [`getnice`] set and get nice levels
+//! - [`Log`] is the file that floods record each finished task in, read back
+//!   as [`LogEntry`]s, in either [`LogFormat`]. [`BufferedLog`] writes
+//!   several at once.
+//!
+//! [`nix_ext`] has the rest of the scheduler and /proc helpers that the binary
+//! is built on. The TUI and the command line are only part of the binary.
+
+// Everything here reads /proc and uses Linux-only scheduler syscalls. The
+// docs can still be built elsewhere.
+#[cfg(all(not(target_os = "linux"), not(doc)))]
+compile_error!("testnice requires Linux /proc");
+
+pub mod log;
+pub mod nix_ext;
+#[doc(hidden)]
+pub mod util;
+
+pub use log::{BufferedLog, Log, LogEntry, LogError, LogFormat};
+pub use nix_ext::{
+    getnice, renice, renice_pid, GetniceError, ReniceError, Sched, SchedCreationError, SchedPolicy,
+};
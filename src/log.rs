@@ -1,26 +1,34 @@
-use nom::{
-    bytes::complete::{tag, take_till},
-    error::Error as NomError,
-    Err as NomErr,
-};
+//! The logfile that floods record each finished task in
+
 use std::{
     cmp,
-    collections::VecDeque,
+    collections::{HashMap, VecDeque},
+    error::Error,
     fmt,
-    fs::{File, OpenOptions},
+    fs::{self, File, OpenOptions},
     io::{self, Read, Seek, SeekFrom, Write},
-    path::PathBuf,
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
     str::FromStr,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant, SystemTime},
 };
 
 use file_guard::FileGuard;
 
+use crate::{nix_ext as nix, util};
+
 #[derive(Debug)]
 pub enum LogError {
     /// Something went wrong with the file io, e.g. we couldn't open the file
     Io(io::Error),
     /// The format of the log file is invalid
     InvalidFormat,
+    /// The header names a [`LogFormat`] that we don't know how to read
+    UnknownFormat(String),
+    /// The logfile is something other than a regular file (e.g. a device or a
+    /// FIFO), so entries logged to it can't be read back
+    NotRegularFile(PathBuf),
 }
 
 impl From<io::Error> for LogError {
@@ -29,30 +37,128 @@ impl From<io::Error> for LogError {
     }
 }
 
-impl<I> From<NomErr<NomError<I>>> for LogError {
-    fn from(_: NomErr<NomError<I>>) -> Self {
-        Self::InvalidFormat
-    }
-}
-
 impl fmt::Display for LogError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Io(..) => write!(f, "while using shared logfile (maybe it was deleted?)"),
             Self::InvalidFormat => write!(f, "corrupted logfile"),
+            Self::UnknownFormat(format) => write!(
+                f,
+                "logfile is in an unknown format `{format}` (expected one of {})",
+                LogFormat::NAMES.join(", ")
+            ),
+            Self::NotRegularFile(path) => write!(
+                f,
+                "{} is not a regular file, so nothing logged to it could be read back",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl Error for LogError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::InvalidFormat | Self::UnknownFormat(..) | Self::NotRegularFile(..) => None,
+        }
+    }
+}
+
+/// How each entry is written to a logfile. This goes in the file's header, so
+/// readers don't need to be told it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `pid@timestamp`, followed by the delimiter
+    #[default]
+    Compact,
+    /// One JSON object per entry, e.g.
+    /// `{"pid":123,"tid":124,"ts":4133589775111,"vruntime":93.734372}`, for
+    /// tailing into
+    /// a log pipeline. This should be delimited by a newline.
+    JsonLines,
+}
+
+impl LogFormat {
+    /// The name of each format in the header, in the same order as the
+    /// variants
+    const NAMES: [&'static str; 2] = ["compact", "json-lines"];
+
+    fn name(self) -> &'static str {
+        Self::NAMES[self as usize]
+    }
+
+    fn from_name(name: &str) -> Result<Self, LogError> {
+        match name {
+            "compact" => Ok(Self::Compact),
+            "json-lines" => Ok(Self::JsonLines),
+            _ => Err(LogError::UnknownFormat(String::from(name))),
         }
     }
 }
 
-/// A shared log file that synchronizes writes
+/// Count how many of `entries` there are for each pid
+pub fn count_by_pid(entries: &[LogEntry]) -> HashMap<i32, usize> {
+    let mut counts = HashMap::new();
+    for entry in entries {
+        *counts.entry(entry.pid).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Count how many of `entries` there are for each thread
+pub fn count_by_tid(entries: &[LogEntry]) -> HashMap<i32, usize> {
+    let mut counts = HashMap::new();
+    for entry in entries {
+        *counts.entry(entry.tid).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// A shared log file that synchronizes writes, or a per-process log file that
+/// only one process writes to, which doesn't need to
 #[derive(Debug, Clone)]
 pub struct Log {
     path: PathBuf,
+    /// Whether we need to take the lock to use this file
+    locked: bool,
+    /// How big the file can get before we drop its oldest entries
+    max_size: u64,
+    /// The byte that we write between entries. Reading uses whichever one is
+    /// in the file's header instead, since another process may have created
+    /// the file.
+    delim: u8,
+    /// How we write entries. Reading also uses the header instead.
+    format: LogFormat,
+    /// The file that entries are written to, opened by the first write and
+    /// shared by every clone, so that writes don't each open it again. This
+    /// also keeps the threads of one process from writing at once, which the
+    /// file lock doesn't, so they should share a clone rather than each
+    /// [`Log::load`] the file. Reading opens the file separately.
+    writer: Arc<Mutex<Option<LogWriter>>>,
+}
+
+/// The file that a [`Log`] writes to, and how long we think it is, so that we
+/// don't have to ask after every write
+#[derive(Debug)]
+struct LogWriter {
+    file: File,
+    /// The length of the file when we opened it or last rotated it, plus
+    /// everything that we have written since. Another process writing to a
+    /// shared file isn't counted, so this can be short of the real length,
+    /// which we only find out once this passes `max_size`.
+    len: u64,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct LogEntry {
     pub pid: i32,
+    /// The thread that completed the task. This is the pid for entries
+    /// written before we recorded threads, and for single-threaded floods.
+    pub tid: i32,
+    /// When the task completed, in nanoseconds on the monotonic clock. This is
+    /// 0 for entries written before we recorded timestamps.
+    pub timestamp: u64,
 }
 
 impl LogEntry {
@@ -60,13 +166,62 @@ impl LogEntry {
     /// This includes the delimiter.
     ///
     /// # Notes
-    /// - Proc IDs for procs we make are typically 5 bytes.  
-    pub const ENCODED_SIZE_ESTIMATE: usize = 8;
+    /// - Proc IDs for procs we make are typically 5 bytes, but can be up to 7
+    ///   bytes if `pid_max` has been raised to its limit of 2^22.
+    /// - Timestamps are typically 13-15 bytes, plus the `@` separator.
+    /// - This only decides how much we read at a time, so larger entries are
+    ///   still read correctly.
+    pub const ENCODED_SIZE_ESTIMATE: usize = 24;
+
+    /// Separates the pid from the timestamp
+    pub const TIMESTAMP_SEP: char = '@';
+
+    /// Separates the pid from the tid, which is only written if it isn't the
+    /// pid
+    pub const TID_SEP: char = '/';
 
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, LogError> {
         let s = std::str::from_utf8(bytes).map_err(|_| LogError::InvalidFormat)?;
         Self::from_str(s)
     }
+
+    /// Parse an entry written in `format`, without its delimiter
+    fn parse(bytes: &[u8], format: LogFormat) -> Result<Self, LogError> {
+        match format {
+            LogFormat::Compact => Self::from_bytes(bytes),
+            LogFormat::JsonLines => Self::from_json(bytes),
+        }
+    }
+
+    /// Parse an entry written as [`LogFormat::JsonLines`]. We only write flat
+    /// objects of numbers, so this doesn't handle anything else. Fields other
+    /// than `pid`, `tid` and `ts` are ignored.
+    fn from_json(bytes: &[u8]) -> Result<Self, LogError> {
+        let s = std::str::from_utf8(bytes).map_err(|_| LogError::InvalidFormat)?;
+        let fields = s
+            .trim()
+            .strip_prefix('{')
+            .and_then(|s| s.strip_suffix('}'))
+            .ok_or(LogError::InvalidFormat)?;
+        let (mut pid, mut tid, mut timestamp) = (None, None, 0);
+        for field in fields.split(',') {
+            let (key, val) = field.split_once(':').ok_or(LogError::InvalidFormat)?;
+            match key.trim() {
+                "\"pid\"" => pid = Some(val.trim().parse().map_err(|_| LogError::InvalidFormat)?),
+                "\"tid\"" => tid = Some(val.trim().parse().map_err(|_| LogError::InvalidFormat)?),
+                "\"ts\"" => timestamp = val.trim().parse().map_err(|_| LogError::InvalidFormat)?,
+                _ => {}
+            }
+        }
+        match (pid, tid.or(pid)) {
+            (Some(pid), Some(tid)) if pid > 0 && tid > 0 => Ok(Self {
+                pid,
+                tid,
+                timestamp,
+            }),
+            _ => Err(LogError::InvalidFormat),
+        }
+    }
 }
 
 impl FromStr for LogEntry {
@@ -74,19 +229,59 @@ impl FromStr for LogEntry {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         // if this ever gets more complicated we should do a nom impl
+        let (pid, timestamp) = match s.trim().split_once(Self::TIMESTAMP_SEP) {
+            Some((pid, timestamp)) => {
+                (pid, timestamp.parse().map_err(|_| LogError::InvalidFormat)?)
+            }
+            // older logfiles don't have timestamps
+            None => (s.trim(), 0),
+        };
+        let (pid, tid) = match pid.split_once(Self::TID_SEP) {
+            Some((pid, tid)) => (pid, Some(tid)),
+            None => (pid, None),
+        };
+        let pid = pid.parse().map_err(|_| LogError::InvalidFormat)?;
+        let tid = match tid {
+            Some(tid) => tid.parse().map_err(|_| LogError::InvalidFormat)?,
+            None => pid,
+        };
+        // `i32::from_str` accepts signs, but a real pid is always positive
+        if pid <= 0 || tid <= 0 {
+            return Err(LogError::InvalidFormat);
+        }
         Ok(Self {
-            pid: s.trim().parse().map_err(|_| LogError::InvalidFormat)?,
+            pid,
+            tid,
+            timestamp,
         })
     }
 }
 
 impl Log {
-    /// The delimeter to be used between entries
+    /// The default delimiter between entries, and the one in logfiles from
+    /// before they had a header
     pub const ENTRY_DELIM: u8 = b'|';
 
+    /// The start of the header line, which is followed by the delimiter as a
+    /// decimal byte and then the [`LogFormat`], e.g.
+    /// `#testnice-log delim=124 format=compact`. Headers from before we wrote
+    /// the format are always compact.
+    const HEADER_PREFIX: &'static [u8] = b"#testnice-log delim=";
+
+    /// The longest header that we will look for
+    const MAX_HEADER_SIZE: usize = 64;
+
     /// The maximum chunk size when processing files
     pub const MAX_CHUNK_SIZE: usize = 1024;
 
+    /// The chunk size when reading a whole file forwards, which is larger
+    /// since we know that we need all of it
+    const TALLY_CHUNK_SIZE: usize = 64 * Self::MAX_CHUNK_SIZE;
+
+    /// The default for how big a logfile can get before we drop its oldest
+    /// entries. At the default step count this takes hours of flooding.
+    pub const MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
     fn open(&self) -> Result<File, LogError> {
         let file = OpenOptions::new()
             .read(true)
@@ -95,107 +290,1054 @@ impl Log {
         Ok(file)
     }
 
+    /// The header line for a logfile whose entries are written in `format`
+    /// and delimited by `delim`
+    fn header(delim: u8, format: LogFormat) -> Vec<u8> {
+        let mut header = Self::HEADER_PREFIX.to_vec();
+        header.extend(format!("{delim} format={}\n", format.name()).as_bytes());
+        header
+    }
+
+    /// Read the delimiter and format from the header of `file`, and where the
+    /// entries start after it. A file without a header is from before we
+    /// wrote one, so its entries start straight away and are compact,
+    /// delimited by [`Log::ENTRY_DELIM`].
+    fn read_header(file: &File) -> Result<(u8, LogFormat, u64), LogError> {
+        let mut buf = [0; Self::MAX_HEADER_SIZE];
+        let mut len = 0;
+        while len < buf.len() {
+            match file.read_at(&mut buf[len..], len as u64)? {
+                0 => break,
+                read => len += read,
+            }
+        }
+        let Some(rest) = buf[..len].strip_prefix(Self::HEADER_PREFIX) else {
+            return Ok((Self::ENTRY_DELIM, LogFormat::Compact, 0));
+        };
+        let newline = rest
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or(LogError::InvalidFormat)?;
+        let header = std::str::from_utf8(&rest[..newline]).map_err(|_| LogError::InvalidFormat)?;
+        let (delim, format) = match header.split_once(' ') {
+            Some((delim, format)) => (delim, format.strip_prefix("format=")),
+            None => (header, Some("compact")),
+        };
+        let delim = delim.parse().map_err(|_| LogError::InvalidFormat)?;
+        let format = LogFormat::from_name(format.ok_or(LogError::InvalidFormat)?)?;
+        let data_start = Self::HEADER_PREFIX.len() + newline + 1;
+        Ok((delim, format, data_start as u64))
+    }
+
+    /// Empty the file at `path`, and write a header for entries written in
+    /// `format` and delimited by `delim`. Nothing is written to `/dev/null`,
+    /// and anything else that isn't a regular file is an error, since we
+    /// couldn't read the entries back (and opening a FIFO would block).
+    fn write_new(path: &Path, delim: u8, format: LogFormat) -> Result<(), LogError> {
+        if Self::is_discarded(path) {
+            return Ok(());
+        }
+        match fs::metadata(path) {
+            Ok(metadata) if !metadata.file_type().is_file() => {
+                return Err(LogError::NotRegularFile(path.to_path_buf()));
+            }
+            _ => {}
+        }
+        File::create(path)?.write_all(&Self::header(delim, format))?;
+        Ok(())
+    }
+
     fn lock(file: &File) -> Result<FileGuard<&File>, LogError> {
         let lock = file_guard::lock(file, file_guard::Lock::Exclusive, 0, isize::MAX as usize)?;
         Ok(lock)
     }
 
     fn write_all(&self, buf: &[u8]) -> Result<(), LogError> {
-        let file = self.open()?;
-        let _lock = Self::lock(&file);
-        (&file).write_all(buf)?;
+        // the file lock doesn't keep out our own threads, but holding the
+        // writer does, so they can't write while we rotate
+        let mut writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let writer_ref = match &mut *writer {
+            Some(writer) => writer,
+            writer => {
+                let file = self.open()?;
+                let len = file.metadata()?.len();
+                writer.insert(LogWriter { file, len })
+            }
+        };
+        let result = Self::write_locked(writer_ref, buf, self.locked, self.max_size);
+        if result.is_err() {
+            // the next write opens the file again, in case it was replaced
+            *writer = None;
+        }
+        result
+    }
+
+    /// Write `buf` to the file, under the lock if it is shared, and rotate the
+    /// file once it grows past `max_size`
+    fn write_locked(
+        writer: &mut LogWriter,
+        buf: &[u8],
+        locked: bool,
+        max_size: u64,
+    ) -> Result<(), LogError> {
+        let file = &writer.file;
+        let _lock = locked.then(|| Self::lock(file)).transpose()?;
+        (&mut &*file).write_all(buf)?;
+        writer.len += buf.len() as u64;
+        if writer.len > max_size {
+            // the file may have been rotated or reset since we last looked
+            writer.len = (&mut &*file).seek(SeekFrom::End(0))?;
+            if writer.len > max_size {
+                writer.len = Self::rotate(file)?;
+            }
+        }
         Ok(())
     }
 
+    /// Drop the older half of the entries in `file`, which must be locked if
+    /// it is shared. This works on the raw bytes, so that a corrupted entry
+    /// can't stop us from bounding the file's size. Returns the new length of
+    /// the file.
+    fn rotate(file: &File) -> Result<u64, LogError> {
+        let (delim, _, data_start) = Self::read_header(file)?;
+        let file = &mut &*file;
+        let len = file.seek(SeekFrom::End(0))?;
+        let middle = data_start + (len - data_start) / 2;
+        file.seek(SeekFrom::Start(middle))?;
+        let mut tail = Vec::with_capacity((len - middle) as usize);
+        file.read_to_end(&mut tail)?;
+        // we are probably in the middle of an entry, so start at the next one
+        let start = tail
+            .iter()
+            .position(|&b| b == delim)
+            .map_or(tail.len(), |i| i + 1);
+        // the header stays
+        file.set_len(data_start)?;
+        file.write_all(&tail[start..])?;
+        Ok(data_start + (tail.len() - start) as u64)
+    }
+
     /// Log the completion of the "CPU-intensive task" that we are doing for a
-    /// given pid.
-    pub fn log_task_completion(&self, pid: i32) -> Result<(), LogError> {
-        let s = format!("{pid}{}", Self::ENTRY_DELIM as char);
-        self.write_all(s.as_bytes())?;
+    /// given pid, on its thread `tid`. This fails with
+    /// [`LogError::InvalidFormat`] if the entry would contain our delimiter,
+    /// since it couldn't be read back.
+    ///
+    /// In [`LogFormat::JsonLines`], the entry also has the `se.vruntime` of
+    /// the thread when the task completed, or `null` if we can't read it.
+    pub fn log_task_completion(&self, pid: i32, tid: i32) -> Result<(), LogError> {
+        if Self::is_discarded(&self.path) {
+            return Ok(());
+        }
+        let entry = self.entry(pid, tid)?;
+        self.write_all(&entry)?;
         Ok(())
     }
 
-    /// Read up to `count` entries from the end of the logfile
-    pub fn read_entries(&self, count: usize) -> Result<VecDeque<LogEntry>, LogError> {
-        /// Process a buffer, outputting all processed entries to `out`. Returns
-        /// the 'remainder'. That is any unprocessed input at the start of the
-        /// buffer that still needs to be processed
-        ///
-        /// Use `upto` to limit the number of entries that are processed.
-        fn process_buf<'a>(
-            buf: &'a [u8],
-            out: &mut VecDeque<LogEntry>,
-            upto: usize,
-        ) -> Result<&'a [u8], LogError> {
-            if buf == [] {
-                return Ok(&[]);
-            }
-
-            let input = buf;
-            let (input, entry_str) = take_till(|b| b == Log::ENTRY_DELIM)(input)?;
-            let (input, _) = tag(&[Log::ENTRY_DELIM])(input)?;
-            let rem = &buf[0..=entry_str.len()];
-
-            let mut chunk = vec![];
-            let mut input = input;
-            loop {
-                let (newinput, entry_str) = take_till(|b| b == Log::ENTRY_DELIM)(input)?;
-                input = newinput;
-
-                if let Ok((newinput, _)) =
-                    tag::<_, &[u8], NomError<&[u8]>>(&[Log::ENTRY_DELIM])(input)
-                {
-                    input = newinput;
-                } else {
-                    // EOF. We do not care about the case where the input starts
-                    // with 0 whitespace and then the delimiter because we handle
-                    // that with rem
-                    break;
-                }
-                if chunk.len() >= upto {
-                    break;
-                }
-
-                chunk.push(LogEntry::from_bytes(entry_str)?);
+    /// The bytes that log a task completing now, in [`Log::log_task_completion`],
+    /// including the delimiter
+    fn entry(&self, pid: i32, tid: i32) -> Result<Vec<u8>, LogError> {
+        let timestamp = nix::monotonic_nanos();
+        let mut entry = match self.format {
+            LogFormat::Compact if tid == pid => {
+                format!("{pid}{}{timestamp}", LogEntry::TIMESTAMP_SEP)
             }
-            for entry in chunk.into_iter().rev() {
-                out.push_front(entry);
+            LogFormat::Compact => format!(
+                "{pid}{}{tid}{}{timestamp}",
+                LogEntry::TID_SEP,
+                LogEntry::TIMESTAMP_SEP
+            ),
+            LogFormat::JsonLines => {
+                let vruntime = nix::Sched::of_thread(pid, tid)
+                    .map_or_else(|_| String::from("null"), |sched| sched.vruntime.to_string());
+                format!(
+                    "{{\"pid\":{pid},\"tid\":{tid},\"ts\":{timestamp},\"vruntime\":{vruntime}}}"
+                )
             }
-
-            Ok(rem)
         }
+        .into_bytes();
+        if entry.contains(&self.delim) {
+            return Err(LogError::InvalidFormat);
+        }
+        entry.push(self.delim);
+        Ok(entry)
+    }
+
+    /// Read up to `count` entries from the end of the logfile. This reads
+    /// backwards a chunk at a time, and stops as soon as it has `count`
+    /// entries, so it only reads about as much of the file as it needs to.
+    pub fn read_entries(&self, count: usize) -> Result<VecDeque<LogEntry>, LogError> {
+        self.read_entries_window(0, count)
+    }
 
+    /// Read up to `count` entries that come before the last `skip`, like
+    /// [`Log::read_entries`] does for the last `count`. Fewer than `count`
+    /// means that the window reaches the start of the logfile.
+    pub fn read_entries_window(
+        &self,
+        skip: usize,
+        count: usize,
+    ) -> Result<VecDeque<LogEntry>, LogError> {
         let file = self.open()?;
         let file = &mut &file;
-        let _lock = Self::lock(file);
+        let _lock = self.locked.then(|| Self::lock(file)).transpose()?;
+        let header = Self::read_header(file)?;
+        Self::read_window(file, header, skip, count)
+    }
 
+    /// [`Log::read_entries_window`] on the entries of `file` after its header,
+    /// which is `(delim, format, data_start)`
+    fn read_window(
+        file: &mut (impl Read + Seek),
+        (delim, format, data_start): (u8, LogFormat, u64),
+        skip: usize,
+        count: usize,
+    ) -> Result<VecDeque<LogEntry>, LogError> {
         let mut entries = VecDeque::with_capacity(count);
-        let mut rem = vec![];
+        if count == 0 {
+            return Ok(entries);
+        }
         let chunk_size = cmp::min(
-            count * LogEntry::ENCODED_SIZE_ESTIMATE,
+            (skip + count) * LogEntry::ENCODED_SIZE_ESTIMATE,
             Self::MAX_CHUNK_SIZE,
         );
-        let mut buf = vec![];
-        let mut seek_offset = file.seek(SeekFrom::End(0))?;
-        while seek_offset > 0 {
-            buf.resize(chunk_size, 0);
-            let to_read = cmp::min(seek_offset as usize, chunk_size);
-            seek_offset -= to_read as u64;
-            file.seek(SeekFrom::Start(seek_offset))?;
-            buf.truncate(to_read);
-            file.read_exact(&mut buf)?;
-            buf.extend(&rem);
-            rem.clear();
-            let upto = count - entries.len();
-            rem.extend(process_buf(&buf, &mut entries, upto)?);
+        // the entries that we have passed over on the way to the window
+        let mut skipped = 0;
+        let mut chunk = vec![0; chunk_size];
+        // the bytes from `offset` up to where we have parsed back to. This
+        // starts with the end of an entry whose start we haven't read yet,
+        // unless we have reached the start of the entries.
+        let mut buf = Vec::with_capacity(2 * chunk_size);
+        let mut offset = file.seek(SeekFrom::End(0))?;
+        while offset > data_start {
+            let to_read = cmp::min(offset - data_start, chunk_size as u64) as usize;
+            offset -= to_read as u64;
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut chunk[..to_read])?;
+            buf.splice(0..0, chunk[..to_read].iter().copied());
+
+            let mut pieces = buf.split(|&b| b == delim).collect::<Vec<_>>();
+            // the piece after the last delimiter is empty, except at the end
+            // of the file, where it is a partially written entry
+            pieces.pop();
+            // the first piece is only a whole entry at the start of the
+            // entries
+            let first = if offset == data_start { 0 } else { 1 };
+            for piece in pieces.iter().skip(first).rev() {
+                if skipped < skip {
+                    skipped += 1;
+                    continue;
+                }
+                entries.push_front(LogEntry::parse(piece, format)?);
+                if entries.len() == count {
+                    return Ok(entries);
+                }
+            }
+            // keep the partial entry (and its delimiter) for the next chunk
+            let partial_len = match pieces.first() {
+                Some(piece) if offset > data_start => piece.len() + 1,
+                _ => 0,
+            };
+            buf.truncate(partial_len);
         }
 
         Ok(entries)
     }
 
-    /// Reset the log file, and return a handle to it (this [`Log`])
-    pub fn create(path: PathBuf) -> Result<Self, LogError> {
-        _ = File::create(&path)?;
-        Ok(Self { path })
+    /// Read the entries from byte `offset` to the end of the logfile. Also
+    /// returns the offset just past the last complete entry, so that passing
+    /// it back in only reads entries logged since.
+    ///
+    /// If the logfile has shrunk below `offset` (e.g. it was recreated) we
+    /// read from the start again. So does an `offset` within the header, e.g.
+    /// 0.
+    pub fn read_entries_from(&self, offset: u64) -> Result<(Vec<LogEntry>, u64), LogError> {
+        let file = self.open()?;
+        let file = &mut &file;
+        let _lock = self.locked.then(|| Self::lock(file)).transpose()?;
+        let (delim, format, data_start) = Self::read_header(file)?;
+
+        let len = file.seek(SeekFrom::End(0))?;
+        let offset = if offset > len || offset < data_start {
+            data_start
+        } else {
+            offset
+        };
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buf = Vec::with_capacity((len - offset) as usize);
+        file.read_to_end(&mut buf)?;
+
+        // anything after the last delimiter is a partially written entry
+        let complete_len = match buf.iter().rposition(|&b| b == delim) {
+            Some(i) => i + 1,
+            None => return Ok((vec![], offset)),
+        };
+        let entries = buf[..complete_len - 1]
+            .split(|&b| b == delim)
+            .map(|piece| LogEntry::parse(piece, format))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok((entries, offset + complete_len as u64))
+    }
+
+    /// Count the entries for each pid in the whole logfile. This reads forwards
+    /// a chunk at a time and only keeps the counts, so it works on logfiles
+    /// of any size. A partially written entry at the end isn't counted, and
+    /// an empty logfile has no counts.
+    pub fn tally(&self) -> Result<HashMap<i32, usize>, LogError> {
+        let file = self.open()?;
+        let file = &mut &file;
+        let _lock = self.locked.then(|| Self::lock(file)).transpose()?;
+        let (delim, format, data_start) = Self::read_header(file)?;
+        file.seek(SeekFrom::Start(data_start))?;
+
+        let mut counts = HashMap::new();
+        let mut chunk = vec![0; Self::TALLY_CHUNK_SIZE];
+        // the bytes of entries that we have read the start of, but not the
+        // delimiter after
+        let mut buf = Vec::with_capacity(2 * Self::TALLY_CHUNK_SIZE);
+        loop {
+            let read = file.read(&mut chunk)?;
+            if read == 0 {
+                break;
+            }
+            buf.extend_from_slice(&chunk[..read]);
+            let Some(last_delim) = buf.iter().rposition(|&b| b == delim) else {
+                continue;
+            };
+            for piece in buf[..last_delim].split(|&b| b == delim) {
+                *counts
+                    .entry(LogEntry::parse(piece, format)?.pid)
+                    .or_insert(0) += 1;
+            }
+            buf.drain(..=last_delim);
+        }
+        Ok(counts)
+    }
+
+    /// Read up to `count` entries that come before the last `skip` of
+    /// several logfiles, merged in the order that they were logged
+    pub fn read_entries_merged(
+        logs: &[Log],
+        skip: usize,
+        count: usize,
+    ) -> Result<VecDeque<LogEntry>, LogError> {
+        if let [log] = logs {
+            return log.read_entries_window(skip, count);
+        }
+        // the window could be entirely in any one of them
+        let mut entries = vec![];
+        for log in logs {
+            entries.extend(log.read_entries(skip + count)?);
+        }
+        entries.sort_by_key(|entry| entry.timestamp);
+        entries.truncate(entries.len().saturating_sub(skip));
+        let start = entries.len().saturating_sub(count);
+        Ok(entries.into_iter().skip(start).collect())
+    }
+
+    /// Count the entries for each pid in several whole logfiles. If `since` is
+    /// given, only count the entries logged within `since` of the newest one
+    /// in any of them.
+    pub fn count_entries_merged(
+        logs: &[Log],
+        since: Option<Duration>,
+    ) -> Result<HashMap<i32, usize>, LogError> {
+        let Some(since) = since else {
+            // without a window, the entries themselves don't matter
+            let mut counts = HashMap::new();
+            for log in logs {
+                for (pid, count) in log.tally()? {
+                    *counts.entry(pid).or_insert(0) += count;
+                }
+            }
+            return Ok(counts);
+        };
+        let mut entries = vec![];
+        for log in logs {
+            entries.extend(log.read_entries_from(0)?.0);
+        }
+        let newest = entries.iter().map(|entry| entry.timestamp).max();
+        let start = newest.unwrap_or(0).saturating_sub(since.as_nanos() as u64);
+        entries.retain(|entry| entry.timestamp >= start);
+        Ok(count_by_pid(&entries))
+    }
+
+    /// Whether `path` is `/dev/null`, which `flood` logs to by default. Nothing
+    /// can be read back from it, so there is no point writing to it.
+    pub fn is_discarded(path: &Path) -> bool {
+        path == Path::new("/dev/null")
+    }
+
+    /// The path of the per-process logfile that goes with the shared logfile
+    /// at `path`, e.g. `/tmp/nicelog.1234`
+    fn shard_path(path: &Path, pid: i32) -> PathBuf {
+        let mut shard_path = path.as_os_str().to_owned();
+        shard_path.push(format!(".{pid}"));
+        PathBuf::from(shard_path)
+    }
+
+    /// A path for a new logfile next to `path`, with the current time (in UTC)
+    /// appended, e.g. `/tmp/nicelog-20240101-120000`. Nothing exists at this
+    /// path yet, so creating a logfile there won't overwrite an older run.
+    pub fn unique_path(path: &Path) -> PathBuf {
+        let now = util::compact_timestamp(SystemTime::now());
+        let mut unique_path = path.as_os_str().to_owned();
+        unique_path.push(format!("-{now}"));
+        let mut n = 1;
+        let mut candidate = PathBuf::from(&unique_path);
+        while candidate.exists() {
+            let mut numbered = unique_path.clone();
+            numbered.push(format!("-{n}"));
+            candidate = PathBuf::from(numbered);
+            n += 1;
+        }
+        candidate
+    }
+
+    /// The path of this logfile
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Return a handle to an existing log file, without resetting it
+    pub fn load(path: PathBuf) -> Result<Self, LogError> {
+        _ = File::open(&path)?;
+        Ok(Self {
+            path,
+            locked: true,
+            max_size: Self::MAX_FILE_SIZE,
+            delim: Self::ENTRY_DELIM,
+            format: LogFormat::Compact,
+            writer: Arc::default(),
+        })
+    }
+
+    /// Return handles to the log file at `path` and all of the per-process
+    /// log files that go with it, without resetting them. It is fine for
+    /// either of these to be missing, but not both.
+    pub fn load_all(path: PathBuf) -> Result<Vec<Self>, LogError> {
+        let mut logs = vec![];
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        if let (Some(name), Ok(dir_entries)) = (path.file_name(), dir.read_dir()) {
+            let prefix = format!("{}.", name.to_string_lossy());
+            for dir_entry in dir_entries.filter_map(|dir_entry| dir_entry.ok()) {
+                let file_name = dir_entry.file_name();
+                let is_shard = file_name
+                    .to_string_lossy()
+                    .strip_prefix(&prefix)
+                    .is_some_and(|pid| pid.parse::<i32>().is_ok());
+                if is_shard {
+                    logs.push(Self {
+                        path: dir_entry.path(),
+                        locked: false,
+                        max_size: Self::MAX_FILE_SIZE,
+                        delim: Self::ENTRY_DELIM,
+                        format: LogFormat::Compact,
+                        writer: Arc::default(),
+                    });
+                }
+            }
+        }
+        match Self::load(path) {
+            Ok(log) => logs.push(log),
+            Err(..) if !logs.is_empty() => {}
+            Err(e) => return Err(e),
+        }
+        Ok(logs)
+    }
+
+    /// Remove all entries from the log file
+    pub fn reset(&self) -> Result<(), LogError> {
+        Self::write_new(&self.path, self.delim, self.format)
+    }
+
+    /// Delete the log file
+    pub fn remove(&self) -> Result<(), LogError> {
+        *self.writer.lock().unwrap_or_else(PoisonError::into_inner) = None;
+        fs::remove_file(&self.path)?;
+        Ok(())
+    }
+
+    /// Reset the log file, and return a handle to it (this [`Log`]). Once it
+    /// grows past `max_size` bytes, the older half of it is dropped. Entries
+    /// are written in `format` and delimited by `delim`, which are both
+    /// written into the file's header so that readers know how to read them.
+    pub fn create(
+        path: PathBuf,
+        max_size: u64,
+        delim: u8,
+        format: LogFormat,
+    ) -> Result<Self, LogError> {
+        Self::write_new(&path, delim, format)?;
+        Ok(Self {
+            path,
+            locked: true,
+            max_size,
+            delim,
+            format,
+            writer: Arc::default(),
+        })
+    }
+
+    /// Reset the per-process log file for `pid` that goes with the shared log
+    /// file at `path`, and return a handle to it. Only `pid` should write to
+    /// it, so writes don't need to take the lock. See [`Log::create`] for
+    /// `max_size`, `delim` and `format`.
+    pub fn create_shard(
+        path: &Path,
+        pid: i32,
+        max_size: u64,
+        delim: u8,
+        format: LogFormat,
+    ) -> Result<Self, LogError> {
+        let path = Self::shard_path(path, pid);
+        Self::write_new(&path, delim, format)?;
+        Ok(Self {
+            path,
+            locked: false,
+            max_size,
+            delim,
+            format,
+            writer: Arc::default(),
+        })
+    }
+
+    /// Return a handle to the per-process log file for `pid` that goes with
+    /// the shared log file at `path`, creating it if `pid` hasn't yet
+    pub fn open_shard(path: &Path, pid: i32) -> Result<Self, LogError> {
+        let path = Self::shard_path(path, pid);
+        _ = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self {
+            path,
+            locked: false,
+            max_size: Self::MAX_FILE_SIZE,
+            delim: Self::ENTRY_DELIM,
+            format: LogFormat::Compact,
+            writer: Arc::default(),
+        })
+    }
+}
+
+/// Completions for a [`Log`] that are written together, under a single lock,
+/// once there are `max_entries` of them or the oldest has waited for
+/// `max_wait`. This is for threads that finish tasks faster than they could
+/// each take the lock for every one. The wait is only checked when another
+/// completion is logged, so call [`BufferedLog::flush`] before going quiet.
+///
+/// Anything still waiting is written when this is dropped, but an error then
+/// is lost, so call [`BufferedLog::flush`] first to see it.
+#[derive(Debug)]
+pub struct BufferedLog {
+    log: Log,
+    buf: Vec<u8>,
+    /// How many entries are in `buf`
+    pending: usize,
+    /// When the first entry in `buf` was logged
+    oldest: Option<Instant>,
+    max_entries: usize,
+    max_wait: Duration,
+}
+
+impl BufferedLog {
+    /// How long a flood's completions wait to be written, at most
+    pub const MAX_WAIT: Duration = Duration::from_millis(100);
+
+    /// Buffer completions for `log`. A `max_entries` of 0 or 1 writes every
+    /// completion straight away, like [`Log::log_task_completion`].
+    pub fn new(log: Log, max_entries: usize, max_wait: Duration) -> Self {
+        Self {
+            log,
+            buf: Vec::new(),
+            pending: 0,
+            oldest: None,
+            max_entries,
+            max_wait,
+        }
+    }
+
+    /// Log the completion of a task like [`Log::log_task_completion`], but
+    /// only write it once the buffer is full or has waited long enough. The
+    /// timestamp is still from now.
+    pub fn log_task_completion(&mut self, pid: i32, tid: i32) -> Result<(), LogError> {
+        if Log::is_discarded(&self.log.path) {
+            return Ok(());
+        }
+        let entry = self.log.entry(pid, tid)?;
+        self.buf.extend_from_slice(&entry);
+        self.pending += 1;
+        let oldest = *self.oldest.get_or_insert_with(Instant::now);
+        if self.pending >= self.max_entries || oldest.elapsed() >= self.max_wait {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write every waiting completion, under a single lock
+    pub fn flush(&mut self) -> Result<(), LogError> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        // a failed write is dropped, rather than written again with the next
+        // batch and maybe duplicated
+        let result = self.log.write_all(&self.buf);
+        self.buf.clear();
+        self.pending = 0;
+        self.oldest = None;
+        result
+    }
+}
+
+impl Drop for BufferedLog {
+    fn drop(&mut self) {
+        _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    /// A path in the temporary directory that no other test uses
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("testnice-{name}-{}", process::id()))
+    }
+
+    fn pids(entries: impl IntoIterator<Item = LogEntry>) -> Vec<i32> {
+        entries.into_iter().map(|entry| entry.pid).collect()
+    }
+
+    /// A new logfile at `path` with an entry for each of `pids`, in order
+    fn log_with(path: &Path, pids: &[i32]) -> Log {
+        let log = Log::create(
+            path.to_path_buf(),
+            Log::MAX_FILE_SIZE,
+            Log::ENTRY_DELIM,
+            LogFormat::Compact,
+        )
+        .unwrap();
+        for &pid in pids {
+            log.log_task_completion(pid, pid).unwrap();
+        }
+        log
+    }
+
+    #[test]
+    fn log_rotates_past_max_size() {
+        let path = temp_path("rotate");
+        let max_size = 256;
+        let log =
+            Log::create(path.clone(), max_size, Log::ENTRY_DELIM, LogFormat::Compact).unwrap();
+        let mut written = 0;
+        for pid in 1..=100 {
+            written += log.entry(pid, pid).unwrap().len();
+            log.log_task_completion(pid, pid).unwrap();
+        }
+        let len = fs::metadata(&path).unwrap().len();
+        assert!(len <= max_size, "{len} bytes is past {max_size}");
+        assert!(len < written as u64);
+        let entries = log.read_entries(1000).unwrap();
+        assert!(entries.len() < 100);
+        assert_eq!(
+            pids(entries.clone()),
+            (101 - entries.len() as i32..=100).collect::<Vec<_>>()
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn read_entries_window_boundaries() {
+        let path = temp_path("window");
+        // enough entries that a window spans several chunks
+        let all = (1..=500).collect::<Vec<_>>();
+        let log = log_with(&path, &all);
+        for (skip, count) in [
+            (0, 0),
+            (0, 1),
+            (0, 499),
+            (0, 500),
+            (0, 501),
+            (1, 1),
+            (10, 0),
+            (10, 90),
+            (43, 200),
+            (499, 1),
+            (499, 2),
+            (500, 1),
+            (600, 10),
+        ] {
+            let end = all.len().saturating_sub(skip);
+            let expected = &all[end.saturating_sub(count)..end];
+            let entries = log.read_entries_window(skip, count).unwrap();
+            assert_eq!(pids(entries), expected, "skip {skip}, count {count}");
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn read_entries_window_skips_partial_entry() {
+        let path = temp_path("partial");
+        let log = log_with(&path, &[1, 2, 3]);
+        // a writer that hasn't finished its entry yet
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"4@12")
+            .unwrap();
+        assert_eq!(pids(log.read_entries(2).unwrap()), [2, 3]);
+        assert_eq!(pids(log.read_entries_window(1, 5).unwrap()), [1, 2]);
+        let (entries, _) = log.read_entries_from(0).unwrap();
+        assert_eq!(pids(entries), [1, 2, 3]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn read_entries_window_empty_log() {
+        let path = temp_path("empty-window");
+        let log = log_with(&path, &[]);
+        assert!(log.read_entries(10).unwrap().is_empty());
+        assert!(log.read_entries_window(5, 10).unwrap().is_empty());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn read_entries_returns_last_k_in_order() {
+        let path = temp_path("last-k");
+        // a xorshift, so that the pids have every length
+        let mut x = 0x2545_f491_4f6c_dd1d_u64;
+        for len in [1_usize, 7, 64, 300] {
+            let pids_written = (0..len)
+                .map(|_| {
+                    x ^= x << 13;
+                    x ^= x >> 7;
+                    x ^= x << 17;
+                    (x % 10u64.pow(1 + (x >> 32) as u32 % 7)).max(1) as i32
+                })
+                .collect::<Vec<_>>();
+            let log = log_with(&path, &pids_written);
+            for k in 0..=len + 1 {
+                let expected = &pids_written[len.saturating_sub(k)..];
+                assert_eq!(pids(log.read_entries(k).unwrap()), expected, "k = {k}");
+            }
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    /// Write `(pid, tid)` entries in `format` and read them back every way
+    /// that we can
+    fn round_trip(name: &str, delim: u8, format: LogFormat) -> String {
+        let path = temp_path(name);
+        let this = process::id() as i32;
+        let this_thread = nix::unistd::gettid().as_raw();
+        let written = [(1, 1), (2, 3), (this, this_thread), (4_194_304, 4_194_303)];
+        let log = Log::create(path.clone(), Log::MAX_FILE_SIZE, delim, format).unwrap();
+        for (pid, tid) in written {
+            log.log_task_completion(pid, tid).unwrap();
+        }
+
+        // a fresh handle, which has to find the format in the header
+        let loaded = Log::load(path.clone()).unwrap();
+        let entries = loaded.read_entries(10).unwrap();
+        let read = entries
+            .iter()
+            .map(|entry| (entry.pid, entry.tid))
+            .collect::<Vec<_>>();
+        assert_eq!(read, written);
+        assert!(entries.iter().all(|entry| entry.timestamp > 0));
+        assert!(entries
+            .iter()
+            .zip(entries.iter().skip(1))
+            .all(|(a, b)| a.timestamp <= b.timestamp));
+        assert_eq!(loaded.read_entries_from(0).unwrap().0, Vec::from(entries));
+        assert_eq!(loaded.tally().unwrap().len(), written.len());
+
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(path).unwrap();
+        contents
+    }
+
+    #[test]
+    fn compact_round_trip() {
+        let contents = round_trip("compact", Log::ENTRY_DELIM, LogFormat::Compact);
+        let entries = contents.lines().nth(1).unwrap();
+        assert!(entries.starts_with("1@"));
+        assert!(entries.contains("|2/3@"));
+    }
+
+    #[test]
+    fn json_lines_round_trip() {
+        let contents = round_trip("json-lines", b'\n', LogFormat::JsonLines);
+        let lines = contents.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "#testnice-log delim=10 format=json-lines");
+        let objects = lines[1..]
+            .iter()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(objects.len(), 4);
+        assert_eq!(objects[1]["tid"], 3);
+        // there is no such thread to read the vruntime of
+        assert!(objects[1]["vruntime"].is_null());
+        // but we can read our own
+        assert!(objects[2]["vruntime"].is_f64());
+    }
+
+    /// The result of reading the entries of a logfile that holds `contents`
+    fn read_contents(name: &str, contents: &str) -> Result<Vec<LogEntry>, LogError> {
+        let path = temp_path(name);
+        fs::write(&path, contents).unwrap();
+        let result = Log::load(path.clone()).and_then(|log| log.read_entries_from(0));
+        fs::remove_file(path).unwrap();
+        result.map(|(entries, _)| entries)
+    }
+
+    #[test]
+    fn header_missing() {
+        // from before logfiles had headers, with and without timestamps
+        let entries = read_contents("no-header", "12@100|13/14@200|15|").unwrap();
+        let entries = entries
+            .iter()
+            .map(|entry| (entry.pid, entry.tid, entry.timestamp))
+            .collect::<Vec<_>>();
+        assert_eq!(entries, [(12, 12, 100), (13, 14, 200), (15, 15, 0)]);
+        // and from before the header named the format
+        let entries = read_contents("old-header", "#testnice-log delim=59\n7@1;8@2;").unwrap();
+        assert_eq!(pids(entries), [7, 8]);
+    }
+
+    #[test]
+    fn header_bad() {
+        for contents in [
+            "#testnice-log delim=124 format=compact",
+            "#testnice-log delim=pipe format=compact\n1@1|",
+            "#testnice-log delim=1024 format=compact\n1@1|",
+            "#testnice-log delim=124 compact\n1@1|",
+        ] {
+            match read_contents("bad-header", contents) {
+                Err(LogError::InvalidFormat) => {}
+                result => panic!("{contents:?}: {result:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn header_unknown_format() {
+        match read_contents("unknown-format", "#testnice-log delim=10 format=csv\n1,1\n") {
+            Err(LogError::UnknownFormat(format)) => assert_eq!(format, "csv"),
+            result => panic!("{result:?}"),
+        }
+    }
+
+    #[test]
+    fn newline_delimited_round_trip() {
+        let contents = round_trip("newline", b'\n', LogFormat::Compact);
+        let lines = contents.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "#testnice-log delim=10 format=compact");
+        assert_eq!(lines.len(), 5);
+        assert!(lines[2].starts_with("2/3@"));
+    }
+
+    #[test]
+    fn read_entries_empty_regular_file() {
+        let path = temp_path("empty-file");
+        fs::write(&path, "").unwrap();
+        let log = Log::load(path.clone()).unwrap();
+        assert!(log.read_entries(10).unwrap().is_empty());
+        assert!(log.read_entries_window(3, 10).unwrap().is_empty());
+        assert_eq!(log.read_entries_from(0).unwrap(), (vec![], 0));
+        assert!(log.tally().unwrap().is_empty());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn create_rejects_non_regular_files() {
+        let path = temp_path("dir");
+        fs::create_dir(&path).unwrap();
+        let result = Log::create(path.clone(), Log::MAX_FILE_SIZE, b'|', LogFormat::Compact);
+        match result {
+            Err(LogError::NotRegularFile(not_file)) => assert_eq!(not_file, path),
+            result => panic!("{result:?}"),
+        }
+        fs::remove_dir(path).unwrap();
+
+        // except /dev/null, which isn't written to at all
+        let discarded = PathBuf::from("/dev/null");
+        let log = Log::create(discarded, Log::MAX_FILE_SIZE, b'|', LogFormat::Compact).unwrap();
+        log.log_task_completion(1, 1).unwrap();
+    }
+
+    #[test]
+    fn large_pids() {
+        // the largest that `pid_max` can be raised to
+        const PID_MAX_LIMIT: i32 = 1 << 22;
+        let entry = LogEntry::from_str("4194304/4194303@6872928065536").unwrap();
+        assert_eq!((entry.pid, entry.tid), (PID_MAX_LIMIT, PID_MAX_LIMIT - 1));
+        assert_eq!(entry.timestamp, 6_872_928_065_536);
+        for s in ["2147483648@1", "1/2147483648@1", "-4194304@1", "0@1"] {
+            assert!(LogEntry::from_str(s).is_err(), "{s}");
+        }
+
+        let path = temp_path("large-pids");
+        let log = log_with(&path, &[PID_MAX_LIMIT; 3]);
+        // an entry of the main thread of the largest pid, with a timestamp
+        // from a day or so of uptime, still fits in the estimate
+        let entry = format!("{PID_MAX_LIMIT}@{}|", 99_999_999_999_999_u64);
+        assert!(entry.len() <= LogEntry::ENCODED_SIZE_ESTIMATE, "{entry}");
+        // and one with a tid doesn't, but is still read back
+        for _ in 0..100 {
+            log.log_task_completion(PID_MAX_LIMIT, PID_MAX_LIMIT - 1)
+                .unwrap();
+        }
+        let entries = log.read_entries(103).unwrap();
+        assert_eq!(entries.len(), 103);
+        assert!(entries.iter().all(|entry| entry.pid == PID_MAX_LIMIT));
+        assert_eq!(
+            entries
+                .iter()
+                .filter(|entry| entry.tid != entry.pid)
+                .count(),
+            100
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    /// The last `count` entries of a compact logfile, by reading all of it
+    fn read_entries_naive(path: &Path, count: usize) -> VecDeque<LogEntry> {
+        let contents = fs::read_to_string(path).unwrap();
+        let entries = match contents.split_once('\n') {
+            Some((header, entries)) if header.starts_with('#') => entries,
+            _ => &contents[..],
+        };
+        let mut pieces = entries.split('|').collect::<Vec<_>>();
+        // after the last delimiter is nothing, or a partial entry
+        pieces.pop();
+        let start = pieces.len().saturating_sub(count);
+        pieces[start..]
+            .iter()
+            .map(|entry| LogEntry::from_str(entry).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn read_entries_matches_naive() {
+        let path = temp_path("naive");
+        let log = log_with(&path, &[]);
+        let mut x = 0x9e37_79b9_7f4a_7c15_u64;
+        for _ in 0..2000 {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            let pid = (x % 10u64.pow(1 + (x >> 32) as u32 % 7)).max(1) as i32;
+            log.log_task_completion(pid, pid + (x >> 63) as i32)
+                .unwrap();
+        }
+        for count in [0, 1, 2, 41, 42, 43, 100, 1000, 1999, 2000, 2001] {
+            assert_eq!(
+                log.read_entries(count).unwrap(),
+                read_entries_naive(&path, count),
+                "count = {count}"
+            );
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    #[ignore = "timed, so run it on its own with --release --ignored"]
+    fn read_entries_performance() {
+        let path = temp_path("performance");
+        for size in [10_000, 1_000_000] {
+            let log = log_with(&path, &[]);
+            let mut logged = BufferedLog::new(log.clone(), 1024, Duration::MAX);
+            for pid in 1..=size {
+                logged.log_task_completion(pid, pid).unwrap();
+            }
+            logged.flush().unwrap();
+            for count in [1, 100, 10_000] {
+                let start = Instant::now();
+                let entries = log.read_entries(count).unwrap();
+                let optimized = start.elapsed();
+                let start = Instant::now();
+                let naive_entries = read_entries_naive(&path, count);
+                let naive = start.elapsed();
+                assert_eq!(entries, naive_entries);
+                eprintln!("{size} entries, last {count}: {optimized:?} (naive {naive:?})");
+                // reading a little of a large file is what this is for
+                if count * 100 <= size as usize {
+                    assert!(optimized < naive, "{optimized:?} vs {naive:?}");
+                }
+            }
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    /// A file that keeps track of what is read from it
+    struct CountingReader {
+        file: File,
+        bytes_read: u64,
+        lowest_offset: u64,
+    }
+
+    impl Read for CountingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let offset = self.file.stream_position()?;
+            let read = self.file.read(buf)?;
+            if read > 0 {
+                self.bytes_read += read as u64;
+                self.lowest_offset = self.lowest_offset.min(offset);
+            }
+            Ok(read)
+        }
+    }
+
+    impl Seek for CountingReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.file.seek(pos)
+        }
+    }
+
+    #[test]
+    fn read_entries_window_reads_only_the_end() {
+        let path = temp_path("read-end");
+        let log = log_with(&path, &[]);
+        let mut logged = BufferedLog::new(log.clone(), 1024, Duration::MAX);
+        for pid in 1..=100_000 {
+            logged.log_task_completion(pid, pid).unwrap();
+        }
+        logged.flush().unwrap();
+
+        let file = File::open(&path).unwrap();
+        let header = Log::read_header(&file).unwrap();
+        let len = file.metadata().unwrap().len();
+        let mut reader = CountingReader {
+            file,
+            bytes_read: 0,
+            lowest_offset: u64::MAX,
+        };
+        let count = 10;
+        let entries = Log::read_window(&mut reader, header, 0, count).unwrap();
+        assert_eq!(pids(entries), (99_991..=100_000).collect::<Vec<_>>());
+
+        // the last entries and the delimiter before them, in whole chunks
+        let contents = fs::read(&path).unwrap();
+        let delims = contents
+            .iter()
+            .enumerate()
+            .filter(|(_, &b)| b == Log::ENTRY_DELIM)
+            .map(|(i, _)| i as u64)
+            .collect::<Vec<_>>();
+        let before_window = delims[delims.len() - 1 - count];
+        let chunk_size = (count * LogEntry::ENCODED_SIZE_ESTIMATE) as u64;
+        let needed = (len - before_window).next_multiple_of(chunk_size);
+        assert!(
+            len - reader.lowest_offset <= needed,
+            "read from {} of {len}, but only needed {needed}",
+            reader.lowest_offset
+        );
+        // and nothing twice
+        assert_eq!(reader.bytes_read, len - reader.lowest_offset);
+        fs::remove_file(path).unwrap();
     }
 }
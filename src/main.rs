@@ -1,22 +1,47 @@
 mod cli;
 mod command;
-mod log;
-mod nix_ext;
+mod config;
+mod report;
+mod theme;
 mod tui;
-mod util;
 
-use clap::Parser;
+use clap::{CommandFactory, FromArgMatches};
 use cli::Cli;
-use command::Exec;
+use command::{AppError, Exec};
+use config::Config;
+// the library's modules, under the names that the rest of the binary uses
+use nice_level::{format_err, format_note, format_warn, log, nix_ext, util};
 
 fn main() {
-    let cli = Cli::parse();
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => exit_with(AppError::Config(e.to_string())),
+    };
+    let matches = config.apply(Cli::command()).get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
     let result = match cli.command {
         cli::Command::Flood(command) => command.exec(),
         cli::Command::Tui(command) => command.exec(),
+        cli::Command::Watch(command) => command.exec(),
+        cli::Command::Compare(command) => command.exec(),
+        cli::Command::Sweep(command) => command.exec(),
+        cli::Command::Stats(command) => command.exec(),
+        cli::Command::Run(command) => command.exec(),
+        cli::Command::Set(command) => command.exec(),
+        cli::Command::Renice(command) => command.exec(),
+        cli::Command::Show(command) => command.exec(),
+        cli::Command::Dump(command) => command.exec(),
+        cli::Command::Watchdiff(command) => command.exec(),
+        cli::Command::Weights(command) => command.exec(),
+        cli::Command::BenchLog(command) => command.exec(),
     };
     if let Err(e) = result {
-        println!("{}", format_err!("{e}"));
-        return;
+        exit_with(e);
     }
 }
+
+/// Print `e` and exit with its [`AppError::exit_code`]
+fn exit_with(e: AppError) -> ! {
+    println!("{}", format_err!("{e}"));
+    std::process::exit(e.exit_code())
+}
@@ -1,13 +1,18 @@
-use nom::{
-    self,
-    bytes::complete::{tag, take_until, take_while},
-};
-use ratatui::{
-    style::{Color as RatatuiColor, Modifier, Style},
-    text::{Line, Span},
-    widgets::Paragraph,
+//! Nice levels, scheduling policies, affinity and the other scheduler state
+//! of a process, mostly from /proc and the scheduler syscalls
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    fs::{self, File},
+    io,
+    ops::RangeInclusive,
+    os::fd::FromRawFd,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
 };
-use std::{error::Error, fmt, fs, str::FromStr};
 
 pub use nix::unistd;
 use nix::{errno::errno, libc};
@@ -19,17 +24,22 @@ pub enum ReniceError {
     Access,
     /// Equivalent to `EPERM`
     Permission,
-    // ESRCH: "no process found" should never happen
+    /// Equivalent to `ESRCH`. This can only happen when renicing another
+    /// process, which may have exited
+    NoSuchProcess(i32),
+    /// Equivalent to `ESRCH` when renicing a process group, which has no
+    /// processes left in it (or never did)
+    NoSuchProcessGroup(i32),
     // EINVAL: "which was invalid" should never happen
 }
 
-pub const EACCES_DESC: &'static str = "\
+pub const EACCES_DESC: &str = "\
     The caller attempted to set a lower nice value (i.e., a \
     higher process priority), but did not have the required \
     privilege (on Linux: did not have the CAP_SYS_NICE \
     capability).";
 
-pub const EPERM_DESC: &'static str = "\
+pub const EPERM_DESC: &str = "\
     A process was located, but its effective user ID did not \
     match either the effective or the real user ID of the \
     caller, and was not privileged (on Linux: did not have the \
@@ -42,6 +52,8 @@ impl fmt::Display for ReniceError {
             Self::Access => write!(f, "{}", EACCES_DESC),
             Self::Permission => write!(f, "{}", EPERM_DESC),
             Self::InvalidNiceLevel(level) => write!(f, "Received invalid nice level: {level}"),
+            Self::NoSuchProcess(pid) => write!(f, "no process with pid {pid}"),
+            Self::NoSuchProcessGroup(pgid) => write!(f, "no process in process group {pgid}"),
         }
     }
 }
@@ -54,21 +66,71 @@ pub const fn is_valid_nice_level(prio: i32) -> bool {
     !(prio > 19 || prio < -20)
 }
 
+/// The kernel's `sched_prio_to_weight` table, from nice -20 to 19. CFS shares a
+/// CPU between runnable tasks in proportion to their weights.
+const NICE_TO_WEIGHT: [u32; 40] = [
+    88761, 71755, 56483, 46273, 36291, // -20
+    29154, 23254, 18705, 14949, 11916, // -15
+    9548, 7620, 6100, 4904, 3906, // -10
+    3121, 2501, 1991, 1586, 1277, // -5
+    1024, 820, 655, 526, 423, // 0
+    335, 272, 215, 172, 137, // 5
+    110, 87, 70, 56, 45, // 10
+    36, 29, 23, 18, 15, // 15
+];
+
+/// The weight that CFS gives to a task at this nice level, which must be valid
+pub const fn nice_weight(prio: i32) -> u32 {
+    NICE_TO_WEIGHT[(prio + 20) as usize]
+}
+
 /// Set the exact nice level of this process. Returns the previous nice level
 /// on success.
 pub fn renice(new_prio: i32) -> std::result::Result<(), ReniceError> {
+    renice_pid(unistd::Pid::this().as_raw(), new_prio)
+}
+
+/// Set the exact nice level of the specified thread. On Linux, this doesn't
+/// change any other threads in its process.
+pub fn renice_pid(pid: i32, new_prio: i32) -> std::result::Result<(), ReniceError> {
+    set_priority(
+        libc::PRIO_PROCESS,
+        pid,
+        new_prio,
+        ReniceError::NoSuchProcess,
+    )
+}
+
+/// Set the exact nice level of every thread of every process in the process
+/// group `pgid`
+pub fn renice_pgid(pgid: i32, new_prio: i32) -> std::result::Result<(), ReniceError> {
+    set_priority(
+        libc::PRIO_PGRP,
+        pgid,
+        new_prio,
+        ReniceError::NoSuchProcessGroup,
+    )
+}
+
+/// setpriority(2), where `no_such` is the error for when `who` doesn't exist
+fn set_priority(
+    which: libc::__priority_which_t,
+    who: i32,
+    new_prio: i32,
+    no_such: fn(i32) -> ReniceError,
+) -> std::result::Result<(), ReniceError> {
     if !is_valid_nice_level(new_prio) {
         return Err(ReniceError::InvalidNiceLevel(new_prio));
     }
 
-    let pid = unistd::Pid::this();
-    let is_err = unsafe { libc::setpriority(libc::PRIO_PROCESS, pid.as_raw() as _, new_prio) };
+    let is_err = unsafe { libc::setpriority(which, who as _, new_prio) };
 
     if is_err == -1 {
         let err = match errno() {
             libc::EACCES => ReniceError::Access,
             libc::EPERM => ReniceError::Permission,
-            _ => unreachable!("ESRCH or EINVAL should never occur"),
+            libc::ESRCH => no_such(who),
+            _ => unreachable!("EINVAL should never occur"),
         };
         return Err(err);
     }
@@ -80,12 +142,26 @@ pub fn renice(new_prio: i32) -> std::result::Result<(), ReniceError> {
 pub enum GetniceError {
     /// Equivalent to `EPERM`
     Permission,
+    /// Equivalent to `EINVAL`
+    InvalidTarget,
+    /// Equivalent to `ESRCH`, which only happens for a process group or user
+    /// that has no processes
+    NoSuchTarget,
 }
 
+pub const GETPRIORITY_EINVAL_DESC: &str = "\
+    The kernel did not accept the target of getpriority(2). This \
+    happens if `which` is not one of PRIO_PROCESS, PRIO_PGRP or \
+    PRIO_USER, or if the process group or user ID does not make \
+    sense for it. See \
+    https://man7.org/linux/man-pages/man2/getpriority.2.html";
+
 impl fmt::Display for GetniceError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Permission => write!(f, "{}", EPERM_DESC),
+            Self::InvalidTarget => write!(f, "{}", GETPRIORITY_EINVAL_DESC),
+            Self::NoSuchTarget => write!(f, "no process matched"),
         }
     }
 }
@@ -94,16 +170,38 @@ impl Error for GetniceError {}
 
 /// Get the exact nice level of the specified process
 pub fn getnice(pid: i32) -> std::result::Result<i32, GetniceError> {
+    get_priority(libc::PRIO_PROCESS, pid as u32)
+}
+
+/// Get the lowest nice level (i.e. the highest priority) of any process in
+/// the process group `pgid`
+pub fn getnice_pgid(pgid: i32) -> std::result::Result<i32, GetniceError> {
+    get_priority(libc::PRIO_PGRP, pgid as u32)
+}
+
+/// Get the lowest nice level (i.e. the highest priority) of any process owned
+/// by the user `uid`
+pub fn getnice_user(uid: u32) -> std::result::Result<i32, GetniceError> {
+    get_priority(libc::PRIO_USER, uid)
+}
+
+/// getpriority(2), which can return -1 on success
+fn get_priority(
+    which: libc::__priority_which_t,
+    who: u32,
+) -> std::result::Result<i32, GetniceError> {
     unsafe {
         *libc::__errno_location() = 0;
     }
-    let prio = unsafe { libc::getpriority(libc::PRIO_PROCESS, pid as u32) };
+    let prio = unsafe { libc::getpriority(which, who) };
 
     let errno = errno();
     if prio == -1 && errno != 0 {
         let err = match errno {
             libc::EPERM => GetniceError::Permission,
-            _ => unreachable!("ESRCH, EINVAL and EACCES should never occur"),
+            libc::EINVAL => GetniceError::InvalidTarget,
+            libc::ESRCH => GetniceError::NoSuchTarget,
+            _ => unreachable!("EACCES should never occur"),
         };
         return Err(err);
     }
@@ -111,45 +209,41 @@ pub fn getnice(pid: i32) -> std::result::Result<i32, GetniceError> {
     Ok(prio)
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum, serde::Serialize)]
 pub enum SchedPolicy {
     /// Represents `SCHED_OTHER`
+    #[default]
+    #[serde(rename = "SCHED_OTHER")]
     Other,
     /// Represents `SCHED_BATCH`
+    #[serde(rename = "SCHED_BATCH")]
     Batch,
     /// Represents `SCHED_IDLE`
+    #[serde(rename = "SCHED_IDLE")]
     Idle,
     /// Represents `SCHED_FIFO`
+    #[serde(rename = "SCHED_FIFO")]
     Fifo,
     /// Represents `SCHED_RR`
+    #[value(name = "rr")]
+    #[serde(rename = "SCHED_RR")]
     RoundRobin,
     /// Represents `SCHED_DEADLINE`
+    #[value(skip)]
+    #[serde(rename = "SCHED_DEADLINE")]
     Deadline,
     /// An unknown policy
+    #[value(skip)]
+    #[serde(rename = "unknown")]
     Unknown,
 }
 
-impl Default for SchedPolicy {
-    fn default() -> Self {
-        Self::Other
-    }
-}
-
 impl FromStr for SchedPolicy {
     type Err = ();
 
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
         let n: i32 = s.parse().map_err(|_| ())?;
-        let policy = match n {
-            libc::SCHED_OTHER => Self::Other,
-            libc::SCHED_BATCH => Self::Batch,
-            libc::SCHED_IDLE => Self::Idle,
-            libc::SCHED_FIFO => Self::Fifo,
-            libc::SCHED_RR => Self::RoundRobin,
-            libc::SCHED_DEADLINE => Self::Deadline,
-            _ => Self::Unknown,
-        };
-        Ok(policy)
+        Ok(Self::from_raw(n))
     }
 }
 
@@ -168,16 +262,363 @@ impl fmt::Display for SchedPolicy {
     }
 }
 
+impl SchedPolicy {
+    /// The policy for a raw `SCHED_*` constant, without any flags
+    pub const fn from_raw(raw: i32) -> Self {
+        match raw {
+            libc::SCHED_OTHER => Self::Other,
+            libc::SCHED_BATCH => Self::Batch,
+            libc::SCHED_IDLE => Self::Idle,
+            libc::SCHED_FIFO => Self::Fifo,
+            libc::SCHED_RR => Self::RoundRobin,
+            libc::SCHED_DEADLINE => Self::Deadline,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// The raw `SCHED_*` constant for this policy, if there is one
+    pub const fn as_raw(&self) -> Option<i32> {
+        match self {
+            Self::Other => Some(libc::SCHED_OTHER),
+            Self::Batch => Some(libc::SCHED_BATCH),
+            Self::Idle => Some(libc::SCHED_IDLE),
+            Self::Fifo => Some(libc::SCHED_FIFO),
+            Self::RoundRobin => Some(libc::SCHED_RR),
+            Self::Deadline => Some(libc::SCHED_DEADLINE),
+            Self::Unknown => None,
+        }
+    }
+
+    /// Is this one of the real-time policies that takes a static priority
+    pub const fn is_realtime(&self) -> bool {
+        matches!(self, Self::Fifo | Self::RoundRobin)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RtPriorityError {
+    /// Only `SCHED_FIFO` and `SCHED_RR` have a real-time priority
+    NotRealtime(SchedPolicy),
+    /// The priority is outside of `sched_get_priority_min..=max`
+    OutOfRange { prio: i32, min: i32, max: i32 },
+    /// `RLIMIT_RTPRIO` is lower than the requested priority and we are not
+    /// privileged
+    Rlimit { prio: i32, limit: u64 },
+}
+
+pub const RTPRIO_RANGE_DESC: &str = "\
+    The real-time priority is outside of the range that the kernel \
+    reports for this policy through sched_get_priority_min(2) and \
+    sched_get_priority_max(2). On Linux, SCHED_FIFO and SCHED_RR \
+    take a priority between 1 (lowest) and 99 (highest).";
+
+pub const RLIMIT_RTPRIO_DESC: &str = "\
+    An unprivileged process may only set a real-time priority up to \
+    its RLIMIT_RTPRIO soft limit, which is usually 0. Run as root (or \
+    with the CAP_SYS_NICE capability), or raise the limit with \
+    `ulimit -r` or /etc/security/limits.conf. See \
+    https://man7.org/linux/man-pages/man2/sched_setscheduler.2.html";
+
+impl fmt::Display for RtPriorityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotRealtime(policy) => {
+                write!(f, "{policy} does not take a real-time priority")
+            }
+            Self::OutOfRange { prio, min, max } => write!(
+                f,
+                "real-time priority {prio} is not in {min}..={max}. {}",
+                RTPRIO_RANGE_DESC
+            ),
+            Self::Rlimit { prio, limit } => write!(
+                f,
+                "real-time priority {prio} exceeds RLIMIT_RTPRIO ({limit}). {}",
+                RLIMIT_RTPRIO_DESC
+            ),
+        }
+    }
+}
+
+impl Error for RtPriorityError {}
+
+/// Get the range of valid static priorities for a real-time policy
+pub fn rt_priority_range(
+    policy: SchedPolicy,
+) -> std::result::Result<RangeInclusive<i32>, RtPriorityError> {
+    let raw = match policy.as_raw() {
+        Some(raw) if policy.is_realtime() => raw,
+        _ => return Err(RtPriorityError::NotRealtime(policy)),
+    };
+    let min = unsafe { libc::sched_get_priority_min(raw) };
+    let max = unsafe { libc::sched_get_priority_max(raw) };
+    if min == -1 || max == -1 {
+        // EINVAL is the only documented error, and we only pass valid policies
+        unreachable!("EINVAL should never occur");
+    }
+    Ok(min..=max)
+}
+
+/// Check that `prio` is a real-time priority that this process is allowed to
+/// set for `policy`, before we get an opaque `EINVAL` or `EPERM` from
+/// `sched_setscheduler`.
+pub fn check_rt_priority(
+    policy: SchedPolicy,
+    prio: i32,
+) -> std::result::Result<(), RtPriorityError> {
+    let range = rt_priority_range(policy)?;
+    if !range.contains(&prio) {
+        return Err(RtPriorityError::OutOfRange {
+            prio,
+            min: *range.start(),
+            max: *range.end(),
+        });
+    }
+
+    // privileged processes are not bound by RLIMIT_RTPRIO
+    if unsafe { libc::geteuid() } == 0 {
+        return Ok(());
+    }
+
+    let mut rlim = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_RTPRIO, &mut rlim) } == 0
+        && rlim.rlim_cur != libc::RLIM_INFINITY
+        && prio as u64 > rlim.rlim_cur
+    {
+        return Err(RtPriorityError::Rlimit {
+            prio,
+            limit: rlim.rlim_cur,
+        });
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyError {
+    /// Equivalent to `EINVAL`
+    Invalid,
+    /// Equivalent to `EPERM`
+    Permission,
+    /// Equivalent to `ESRCH`
+    NoSuchProcess(i32),
+    /// The real-time priority can't be used with the policy, which we check
+    /// before asking the kernel
+    RtPriority(RtPriorityError),
+}
+
+pub const SCHED_EPERM_DESC: &str = "\
+    The caller does not have appropriate privileges to set this \
+    scheduling policy (on Linux: did not have the CAP_SYS_NICE \
+    capability), or its effective user ID does not match that of the \
+    target process. See \
+    https://man7.org/linux/man-pages/man2/sched_setscheduler.2.html";
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid => write!(f, "invalid scheduling policy or parameters"),
+            Self::Permission => write!(f, "{}", SCHED_EPERM_DESC),
+            Self::NoSuchProcess(pid) => write!(f, "no process with pid {pid}"),
+            Self::RtPriority(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl From<RtPriorityError> for PolicyError {
+    fn from(value: RtPriorityError) -> Self {
+        Self::RtPriority(value)
+    }
+}
+
+impl Error for PolicyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::RtPriority(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+fn policy_error(pid: i32) -> PolicyError {
+    match errno() {
+        libc::EINVAL => PolicyError::Invalid,
+        libc::EPERM => PolicyError::Permission,
+        libc::ESRCH => PolicyError::NoSuchProcess(pid),
+        _ => unreachable!("EFAULT should never occur"),
+    }
+}
+
+/// Get the raw policy of `pid`, including the `SCHED_RESET_ON_FORK` flag. A
+/// `pid` of 0 means the calling thread.
+fn getscheduler(pid: i32) -> std::result::Result<i32, PolicyError> {
+    let policy = unsafe { libc::sched_getscheduler(pid) };
+    if policy == -1 {
+        return Err(policy_error(pid));
+    }
+    Ok(policy)
+}
+
+/// Get the policy of `pid`. A `pid` of 0 means the calling thread.
+pub fn get_policy(pid: i32) -> std::result::Result<SchedPolicy, PolicyError> {
+    Ok(SchedPolicy::from_raw(
+        getscheduler(pid)? & !libc::SCHED_RESET_ON_FORK,
+    ))
+}
+
+/// Get the static priority of `pid`, which is only meaningful for the
+/// real-time policies and 0 otherwise. A `pid` of 0 means the calling thread.
+pub fn rt_priority(pid: i32) -> std::result::Result<i32, PolicyError> {
+    let mut param = libc::sched_param { sched_priority: 0 };
+    if unsafe { libc::sched_getparam(pid, &mut param) } == -1 {
+        return Err(policy_error(pid));
+    }
+    Ok(param.sched_priority)
+}
+
+/// Check whether `pid` has the `SCHED_RESET_ON_FORK` flag set. A `pid` of 0
+/// means the calling thread.
+pub fn reset_on_fork(pid: i32) -> std::result::Result<bool, PolicyError> {
+    Ok(getscheduler(pid)? & libc::SCHED_RESET_ON_FORK != 0)
+}
+
+/// Set the policy of `pid`, keeping its `SCHED_RESET_ON_FORK` flag. `rt_prio`
+/// is the static priority for `SCHED_FIFO` and `SCHED_RR`, and must be 0 for
+/// every other policy. A `pid` of 0 means the calling thread.
+pub fn set_policy(
+    pid: i32,
+    policy: SchedPolicy,
+    rt_prio: i32,
+) -> std::result::Result<(), PolicyError> {
+    // SCHED_DEADLINE needs sched_setattr(2), which takes a runtime, deadline
+    // and period instead
+    let raw = match policy.as_raw() {
+        Some(raw) if policy != SchedPolicy::Deadline => raw,
+        _ => return Err(PolicyError::Invalid),
+    };
+    if policy.is_realtime() {
+        check_rt_priority(policy, rt_prio)?;
+    } else if rt_prio != 0 {
+        return Err(RtPriorityError::NotRealtime(policy).into());
+    }
+    let flags = getscheduler(pid)? & libc::SCHED_RESET_ON_FORK;
+    let param = libc::sched_param {
+        sched_priority: rt_prio,
+    };
+    if unsafe { libc::sched_setscheduler(pid, raw | flags, &param) } == -1 {
+        return Err(policy_error(pid));
+    }
+    Ok(())
+}
+
+/// Set the `SCHED_RESET_ON_FORK` flag on `pid`, keeping its current policy and
+/// priority. Children forked after this do not inherit a real-time policy or a
+/// negative nice level. A `pid` of 0 means the calling thread.
+pub fn set_reset_on_fork(pid: i32) -> std::result::Result<(), PolicyError> {
+    let policy = getscheduler(pid)?;
+    let mut param = libc::sched_param { sched_priority: 0 };
+    if unsafe { libc::sched_getparam(pid, &mut param) } == -1 {
+        return Err(policy_error(pid));
+    }
+
+    let is_err =
+        unsafe { libc::sched_setscheduler(pid, policy | libc::SCHED_RESET_ON_FORK, &param) };
+    if is_err == -1 {
+        return Err(policy_error(pid));
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AffinityError {
+    /// The cpu index does not fit in a `cpu_set_t`
+    InvalidCpu(usize),
+    /// Equivalent to `EINVAL`: the mask contains no CPUs that are online and
+    /// allowed for the process
+    Unavailable,
+    /// Equivalent to `EPERM`
+    Permission,
+    /// Equivalent to `ESRCH`
+    NoSuchProcess(i32),
+}
+
+impl fmt::Display for AffinityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidCpu(cpu) => write!(f, "cpu {cpu} is not a valid cpu index"),
+            Self::Unavailable => write!(
+                f,
+                "none of the requested cpus are online and allowed for the process"
+            ),
+            Self::Permission => write!(f, "{}", EPERM_DESC),
+            Self::NoSuchProcess(pid) => write!(f, "no process with pid {pid}"),
+        }
+    }
+}
+
+impl Error for AffinityError {}
+
+fn affinity_error(pid: i32) -> AffinityError {
+    match errno() {
+        libc::EINVAL => AffinityError::Unavailable,
+        libc::EPERM => AffinityError::Permission,
+        libc::ESRCH => AffinityError::NoSuchProcess(pid),
+        _ => unreachable!("EFAULT should never occur"),
+    }
+}
+
+/// Restrict the process (or thread) `pid` to run on the given cpus. A `pid` of
+/// 0 means the calling thread.
+pub fn set_affinity(pid: i32, cpus: &[usize]) -> std::result::Result<(), AffinityError> {
+    let mut set = unsafe { std::mem::zeroed::<libc::cpu_set_t>() };
+    for &cpu in cpus {
+        if cpu >= libc::CPU_SETSIZE as usize {
+            return Err(AffinityError::InvalidCpu(cpu));
+        }
+        unsafe { libc::CPU_SET(cpu, &mut set) };
+    }
+
+    let is_err =
+        unsafe { libc::sched_setaffinity(pid, std::mem::size_of::<libc::cpu_set_t>(), &set) };
+    if is_err == -1 {
+        return Err(affinity_error(pid));
+    }
+
+    Ok(())
+}
+
+/// Get the cpus that the process (or thread) `pid` is allowed to run on. A
+/// `pid` of 0 means the calling thread.
+pub fn get_affinity(pid: i32) -> std::result::Result<Vec<usize>, AffinityError> {
+    let mut set = unsafe { std::mem::zeroed::<libc::cpu_set_t>() };
+    let is_err =
+        unsafe { libc::sched_getaffinity(pid, std::mem::size_of::<libc::cpu_set_t>(), &mut set) };
+    if is_err == -1 {
+        return Err(affinity_error(pid));
+    }
+
+    Ok((0..libc::CPU_SETSIZE as usize)
+        .filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &set) })
+        .collect())
+}
+
 #[non_exhaustive]
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Default, serde::Serialize)]
 pub struct Sched {
     /// `se.exec_start`
+    #[serde(rename = "se.exec_start")]
     pub exec_start: f64,
     /// `se.vruntime`
+    #[serde(rename = "se.vruntime")]
     pub vruntime: f64,
     /// `se.sum_exec_runtime`
+    #[serde(rename = "se.sum_exec_runtime")]
     pub sum_exec_runtime: f64,
     /// `se.nr_migrations`
+    #[serde(rename = "se.nr_migrations")]
     pub nr_migrations: u64,
     /// `nr_switches`
     pub nr_switches: u64,
@@ -186,40 +627,56 @@ pub struct Sched {
     /// `nr_involuntary_switches`
     pub nr_involuntary_switches: u64,
     /// `se.load.weight`
+    #[serde(rename = "se.load.weight")]
     pub load_weight: u64,
     /// `se.avg.load_sum`
+    #[serde(rename = "se.avg.load_sum")]
     pub avg_load_sum: u64,
     /// `se.avg.runnable_sum`
+    #[serde(rename = "se.avg.runnable_sum")]
     pub avg_runnable_sum: u64,
     /// `se.avg.util_sum`
+    #[serde(rename = "se.avg.util_sum")]
     pub avg_util_sum: u64,
     /// `se.avg.load_avg`
+    #[serde(rename = "se.avg.load_avg")]
     pub avg_load_avg: u64,
     /// `se.avg.runnable_avg`
+    #[serde(rename = "se.avg.runnable_avg")]
     pub avg_runnable_avg: u64,
     /// `se.avg.util_avg`
+    #[serde(rename = "se.avg.util_avg")]
     pub avg_util_avg: u64,
     /// `se.avg.last_update_time`
+    #[serde(rename = "se.avg.last_update_time")]
     pub avg_last_update_time: u64,
     /// `se.avg.util_est.ewma`
+    #[serde(rename = "se.avg.util_est.ewma")]
     pub avg_util_est_ewma: u64,
     /// `se.avg.util_est.enqueued`
+    #[serde(rename = "se.avg.util_est.enqueued")]
     pub avg_util_est_enqueued: u64,
     /// `uclamp.min`
+    #[serde(rename = "uclamp.min")]
     pub uclamp_min: u64,
     /// `uclamp.max`
+    #[serde(rename = "uclamp.max")]
     pub uclamp_max: u64,
     /// `effective uclamp.min`
+    #[serde(rename = "effective uclamp.min")]
     pub effective_uclamp_min: u64,
     /// `effective uclamp.max`
+    #[serde(rename = "effective uclamp.max")]
     pub effective_uclamp_max: u64,
     /// `policy`
     pub policy: SchedPolicy,
     /// `prio`
     pub prio: u64,
     /// `clock-delta`
+    #[serde(rename = "clock-delta")]
     pub clock_delta: u64,
     /// `mm->numa_scan_seq`
+    #[serde(rename = "mm->numa_scan_seq")]
     pub numa_scan_seq: u64,
     /// `numa_pages_migrated`
     pub numa_pages_migrated: u64,
@@ -227,8 +684,651 @@ pub struct Sched {
     pub numa_preferred_nid: i64,
     /// `total_numa_faults`
     pub total_numa_faults: u64,
+    /// `stats.wait_sum` (or `se.statistics.wait_sum` on older kernels), the
+    /// total time spent runnable but waiting for a CPU. Like the other
+    /// schedstats fields, this is 0 unless the kernel collects schedstats.
+    #[serde(rename = "stats.wait_sum")]
+    pub wait_sum: f64,
+    /// `stats.wait_count`, how many times we have waited for a CPU
+    #[serde(rename = "stats.wait_count")]
+    pub wait_count: u64,
+    /// `stats.nr_forced_migrations`, how many times we were migrated while
+    /// still cache-hot because the balancer had no better choice
+    #[serde(rename = "stats.nr_forced_migrations")]
+    pub nr_forced_migrations: u64,
     /// The nice value of this process -- this is not normally in `Sched`
     pub ni: i32,
+    /// Whether `SCHED_RESET_ON_FORK` is set -- this is not normally in `Sched`
+    pub reset_on_fork: bool,
+    /// The kernel function this process is waiting in, from `wchan` -- this is
+    /// not normally in `Sched`
+    pub wchan: String,
+    /// The timer slack of this process in nanoseconds, from `timerslack_ns` --
+    /// this is not normally in `Sched`
+    #[serde(rename = "timer_slack_ns")]
+    pub timer_slack: u64,
+    /// The fields that we couldn't parse, which are left as their defaults
+    pub unknown: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcfsError {
+    /// `/proc` does not exist or we cannot stat it
+    Missing,
+    /// `/proc` exists but something other than procfs is mounted there
+    NotProcfs,
+    /// We are not on Linux, so there is no `/proc/[pid]/sched` to read
+    #[cfg_attr(target_os = "linux", allow(unused))]
+    NotLinux,
+}
+
+pub const PROCFS_DESC: &str = "\
+    testnice reads /proc/[pid]/sched to inspect processes, so it \
+    needs procfs mounted at /proc. Minimal containers sometimes \
+    leave it out; mount it with `mount -t proc proc /proc`.";
+
+impl fmt::Display for ProcfsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing => write!(f, "/proc is not available. {}", PROCFS_DESC),
+            Self::NotProcfs => write!(f, "/proc is not a procfs mount. {}", PROCFS_DESC),
+            Self::NotLinux => write!(f, "testnice requires Linux /proc"),
+        }
+    }
+}
+
+impl Error for ProcfsError {}
+
+/// Check that procfs is mounted at `/proc`
+#[cfg(target_os = "linux")]
+pub fn check_procfs() -> std::result::Result<(), ProcfsError> {
+    let mut stat = unsafe { std::mem::zeroed::<libc::statfs>() };
+    let is_err = unsafe { libc::statfs(c"/proc".as_ptr(), &mut stat) };
+    if is_err == -1 {
+        return Err(ProcfsError::Missing);
+    }
+    if stat.f_type != libc::PROC_SUPER_MAGIC {
+        return Err(ProcfsError::NotProcfs);
+    }
+    Ok(())
+}
+
+/// Only Linux has the procfs that we read
+#[cfg(not(target_os = "linux"))]
+pub fn check_procfs() -> std::result::Result<(), ProcfsError> {
+    Err(ProcfsError::NotLinux)
+}
+
+/// Create a pipe for a child process to write to, returning `(read, write)`.
+/// The read end doesn't block. The write end is inherited by any child that
+/// we spawn while it is open, so it should be closed once the child has been
+/// spawned.
+pub fn inherited_pipe() -> io::Result<(File, File)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let (read, write) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    // the write end should block like a normal file, and stay open across exec
+    let flags = unsafe { libc::fcntl(fds[1], libc::F_GETFL) };
+    if unsafe { libc::fcntl(fds[1], libc::F_SETFL, flags & !libc::O_NONBLOCK) } == -1
+        || unsafe { libc::fcntl(fds[1], libc::F_SETFD, 0) } == -1
+    {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((read, write))
+}
+
+/// `CAP_SYS_NICE` from `linux/capability.h`
+const CAP_SYS_NICE: u32 = 23;
+
+/// Check whether this process has the capability `cap` in its effective set.
+/// If we can't tell, we assume that it doesn't.
+fn has_capability(cap: u32) -> bool {
+    let Ok(status) = fs::read_to_string("/proc/self/status") else {
+        return false;
+    };
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+        .is_some_and(|caps| caps & (1 << cap) != 0)
+}
+
+/// Check whether this process has `CAP_SYS_NICE`, which it needs to lower nice
+/// levels or use the real-time policies
+pub fn has_cap_sys_nice() -> bool {
+    has_capability(CAP_SYS_NICE)
+}
+
+/// Check whether this process can read the /sched files of processes owned by
+/// other users, by trying to read init's
+pub fn can_read_foreign_sched() -> bool {
+    fs::read_to_string("/proc/1/sched").is_ok()
+}
+
+/// Check whether this is a PREEMPT_RT kernel. Newer kernels say so in sysfs,
+/// and older ones only in the version string that `uname -v` prints.
+pub fn is_preempt_rt() -> bool {
+    if let Ok(realtime) = fs::read_to_string("/sys/kernel/realtime") {
+        return realtime.trim() == "1";
+    }
+    fs::read_to_string("/proc/sys/kernel/version")
+        .is_ok_and(|version| version.contains("PREEMPT_RT"))
+}
+
+/// Set once we have received SIGINT or SIGTERM
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(signal: libc::c_int) {
+    // the first interrupt asks us to stop, the second one means it
+    if INTERRUPTED.swap(true, Ordering::SeqCst) {
+        unsafe { libc::_exit(128 + signal) };
+    }
+}
+
+/// Handle SIGINT and SIGTERM by setting a flag, which can be checked with
+/// [`interrupted`], instead of dying. A second signal exits immediately, in
+/// case we never get around to checking.
+pub fn catch_interrupt() {
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // this can only fail for an invalid signal number
+        unsafe {
+            libc::signal(
+                signal,
+                on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            )
+        };
+    }
+}
+
+/// Whether we have received SIGINT or SIGTERM since calling
+/// [`catch_interrupt`]
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Toggled each time we receive SIGUSR1
+static LOGGING_PAUSED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_pause_toggle(_: libc::c_int) {
+    LOGGING_PAUSED.fetch_xor(true, Ordering::SeqCst);
+}
+
+/// Handle SIGUSR1 by pausing or resuming logging, which can be checked with
+/// [`logging_paused`], instead of dying. This is how the tui pauses its
+/// workers' logging without stopping their work.
+pub fn catch_pause_toggle() {
+    // this can only fail for an invalid signal number
+    unsafe {
+        libc::signal(
+            libc::SIGUSR1,
+            on_pause_toggle as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        )
+    };
+}
+
+/// Whether we have received an odd number of SIGUSR1s since calling
+/// [`catch_pause_toggle`]
+pub fn logging_paused() -> bool {
+    LOGGING_PAUSED.load(Ordering::SeqCst)
+}
+
+/// Ask the flood process `pid` to pause logging, or to resume it if it is
+/// paused
+pub fn toggle_logging(pid: libc::pid_t) -> io::Result<()> {
+    if unsafe { libc::kill(pid, libc::SIGUSR1) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The current time on the monotonic clock, in nanoseconds. This is consistent
+/// between processes, so it can be used to order events from several processes.
+pub fn monotonic_nanos() -> u64 {
+    let mut ts = unsafe { std::mem::zeroed::<libc::timespec>() };
+    // CLOCK_MONOTONIC is always supported, so this cannot fail
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+/// The kernel function that `pid` is waiting in, from `/proc/[pid]/wchan`.
+/// This is `running` if the process isn't waiting, which for a CPU hog is most
+/// of the time.
+pub fn wchan(pid: libc::pid_t) -> io::Result<String> {
+    let wchan = fs::read_to_string(format!("/proc/{pid}/wchan"))?;
+    let wchan = wchan.trim();
+    Ok(match wchan {
+        "" | "0" => String::from("running"),
+        _ => String::from(wchan),
+    })
+}
+
+/// The CPU time used by the calling thread, in nanoseconds. This is the same
+/// as its `se.sum_exec_runtime`.
+pub fn thread_cpu_nanos() -> u64 {
+    let mut ts = unsafe { std::mem::zeroed::<libc::timespec>() };
+    // CLOCK_THREAD_CPUTIME_ID is always supported, so this cannot fail
+    unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+/// The nice level of the autogroup that `pid` is in, from
+/// `/proc/[pid]/autogroup`. This only matters if autogrouping is enabled.
+pub fn autogroup_nice(pid: libc::pid_t) -> io::Result<i32> {
+    let autogroup = fs::read_to_string(format!("/proc/{pid}/autogroup"))?;
+    autogroup
+        .split_once(" nice ")
+        .and_then(|(_, nice)| nice.trim().parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unexpected autogroup format"))
+}
+
+/// The OOM killer adjustment of `pid`, from `/proc/[pid]/oom_score_adj`
+pub fn oom_score_adj(pid: libc::pid_t) -> io::Result<i32> {
+    fs::read_to_string(format!("/proc/{pid}/oom_score_adj"))?
+        .trim()
+        .parse()
+        .map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected oom_score_adj format",
+            )
+        })
+}
+
+/// The timer slack of `pid` in nanoseconds, from `/proc/[pid]/timerslack_ns`.
+/// This is how late the kernel may wake the process from a sleep, so that it
+/// can group wakeups together.
+pub fn timer_slack(pid: libc::pid_t) -> io::Result<u64> {
+    fs::read_to_string(format!("/proc/{pid}/timerslack_ns"))?
+        .trim()
+        .parse()
+        .map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected timerslack_ns format",
+            )
+        })
+}
+
+/// Set the timer slack of the calling thread, which threads that it spawns
+/// inherit. A slack of 0 resets it to the default (normally 50us).
+pub fn set_timer_slack(ns: u64) -> io::Result<()> {
+    if unsafe { libc::prctl(libc::PR_SET_TIMERSLACK, ns as libc::c_ulong) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The files in `pid`'s cgroup that limit how much CPU it gets, as
+/// `(file_name, contents)` pairs. These are `cpu.weight` and `cpu.max` on
+/// cgroup v2, or `cpu.shares` and the CFS quota on cgroup v1. Any that can't be
+/// read are left out.
+pub fn cgroup_cpu_limits(pid: libc::pid_t) -> io::Result<Vec<(&'static str, String)>> {
+    let cgroups = fs::read_to_string(format!("/proc/{pid}/cgroup"))?;
+    let mut limits = vec![];
+    for line in cgroups.lines() {
+        // each line is `hierarchy-id:controllers:path`
+        let mut parts = line.splitn(3, ':');
+        let (Some(id), Some(controllers), Some(path)) = (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        let path = path.trim_start_matches('/');
+        let (dir, file_names): (_, &[_]) = if id == "0" && controllers.is_empty() {
+            (
+                Path::new("/sys/fs/cgroup").join(path),
+                &["cpu.weight", "cpu.max"],
+            )
+        } else if controllers.split(',').any(|controller| controller == "cpu") {
+            (
+                Path::new("/sys/fs/cgroup/cpu").join(path),
+                &["cpu.shares", "cpu.cfs_quota_us", "cpu.cfs_period_us"],
+            )
+        } else {
+            continue;
+        };
+        for file_name in file_names {
+            if let Ok(contents) = fs::read_to_string(dir.join(file_name)) {
+                limits.push((*file_name, String::from(contents.trim())));
+            }
+        }
+    }
+    Ok(limits)
+}
+
+#[derive(Debug)]
+pub enum CgroupError {
+    /// cgroup v2 isn't mounted at `/sys/fs/cgroup`, or it doesn't have the cpu
+    /// controller
+    Unsupported,
+    /// We couldn't create, configure or join the cgroup -- probably bad
+    /// permissions
+    Io(io::Error),
+}
+
+impl From<io::Error> for CgroupError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl fmt::Display for CgroupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsupported => write!(
+                f,
+                "this needs cgroup v2 mounted at /sys/fs/cgroup, with the cpu controller"
+            ),
+            Self::Io(e) => write!(f, "while setting up a cgroup ({e})"),
+        }
+    }
+}
+
+impl Error for CgroupError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Unsupported => None,
+        }
+    }
+}
+
+/// A cgroup v2 cgroup that we created for just the calling process. When this
+/// is dropped, the process moves back to the cgroup it was in and this one is
+/// removed. If the process is killed instead, the empty cgroup is left behind
+/// as `/sys/fs/cgroup/testnice-<pid>`.
+pub struct TransientCgroup {
+    dir: PathBuf,
+    original: PathBuf,
+}
+
+impl TransientCgroup {
+    const ROOT: &'static str = "/sys/fs/cgroup";
+
+    /// Move the calling process into a new cgroup with `cpu.weight.nice` set to
+    /// `ni`. This is the cgroup's weight expressed as a nice level, and is
+    /// applied on top of the process's own nice level. The cgroup is made
+    /// under the root, since that is the only cgroup that can have both
+    /// processes and children with the cpu controller.
+    pub fn join_with_weight_nice(ni: i32) -> std::result::Result<Self, CgroupError> {
+        let root = Path::new(Self::ROOT);
+        // cgroup.controllers only exists on cgroup v2
+        let controllers = fs::read_to_string(root.join("cgroup.controllers"))
+            .map_err(|_| CgroupError::Unsupported)?;
+        if !controllers
+            .split_whitespace()
+            .any(|controller| controller == "cpu")
+        {
+            return Err(CgroupError::Unsupported);
+        }
+        fs::write(root.join("cgroup.subtree_control"), "+cpu")?;
+
+        // the v2 hierarchy is the line that looks like `0::path`
+        let cgroups = fs::read_to_string("/proc/self/cgroup")?;
+        let original = cgroups
+            .lines()
+            .find_map(|line| line.strip_prefix("0::"))
+            .ok_or(CgroupError::Unsupported)?;
+        let pid = unistd::Pid::this().as_raw();
+        let dir = root.join(format!("testnice-{pid}"));
+        fs::create_dir(&dir)?;
+        // from here on, dropping this cleans up
+        let cgroup = Self {
+            dir,
+            original: root.join(original.trim_start_matches('/')),
+        };
+        fs::write(cgroup.dir.join("cpu.weight.nice"), ni.to_string())?;
+        fs::write(cgroup.dir.join("cgroup.procs"), pid.to_string())?;
+        Ok(cgroup)
+    }
+}
+
+impl Drop for TransientCgroup {
+    fn drop(&mut self) {
+        let pid = unistd::Pid::this().as_raw();
+        _ = fs::write(self.original.join("cgroup.procs"), pid.to_string());
+        _ = fs::remove_dir(&self.dir);
+    }
+}
+
+/// The scheduler statistics of a thread, from `schedstat`. All times are in
+/// nanoseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SchedStat {
+    /// Time spent running on a CPU
+    pub run_time: u64,
+    /// Time spent runnable but waiting for a CPU
+    pub wait_time: u64,
+    /// How many times we were given a CPU
+    pub timeslices: u64,
+}
+
+impl SchedStat {
+    fn read(path: &Path) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let mut fields = contents
+            .split_whitespace()
+            .map(|field| field.parse::<u64>());
+        let mut next = || match fields.next() {
+            Some(Ok(field)) => Ok(field),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected schedstat format",
+            )),
+        };
+        Ok(Self {
+            run_time: next()?,
+            wait_time: next()?,
+            timeslices: next()?,
+        })
+    }
+
+    /// The statistics of every thread of the calling process except the
+    /// calling thread, added together. This is for sampling the other threads
+    /// without counting the sampler.
+    pub fn of_other_threads() -> io::Result<Self> {
+        let this = unsafe { libc::gettid() }.to_string();
+        let mut total = Self::default();
+        for entry in fs::read_dir("/proc/self/task")? {
+            let entry = entry?;
+            if entry.file_name() == this.as_str() {
+                continue;
+            }
+            // a thread may exit between listing and reading it
+            let Ok(stat) = Self::read(&entry.path().join("schedstat")) else {
+                continue;
+            };
+            total.run_time += stat.run_time;
+            total.wait_time += stat.wait_time;
+            total.timeslices += stat.timeslices;
+        }
+        Ok(total)
+    }
+}
+
+/// A process that we found in /proc
+pub struct ProcessEntry {
+    pub pid: i32,
+    /// The name of the process, which the kernel cuts short to 15 bytes
+    pub comm: String,
+}
+
+/// Every userspace process, skipping kernel threads. Processes that exit while
+/// we are listing them are left out.
+pub fn user_processes() -> io::Result<Vec<ProcessEntry>> {
+    let mut processes = vec![];
+    for entry in fs::read_dir("/proc")? {
+        let entry = entry?;
+        let Some(pid) = entry.file_name().to_str().and_then(|pid| pid.parse().ok()) else {
+            continue;
+        };
+        // kernel threads have no command line. Neither do zombies, but there
+        // is nothing to renice in those either.
+        let (Ok(comm), Ok(cmdline)) = (
+            fs::read_to_string(entry.path().join("comm")),
+            fs::read(entry.path().join("cmdline")),
+        ) else {
+            continue;
+        };
+        if cmdline.is_empty() {
+            continue;
+        }
+        processes.push(ProcessEntry {
+            pid,
+            comm: String::from(comm.trim_end_matches('\n')),
+        });
+    }
+    processes.sort_by_key(|process| process.pid);
+    Ok(processes)
+}
+
+/// The ids of every thread in a process, in order. The main thread's is the
+/// pid, which comes first.
+pub fn thread_ids(pid: i32) -> io::Result<Vec<i32>> {
+    let mut tids = vec![];
+    for entry in fs::read_dir(format!("/proc/{pid}/task"))? {
+        if let Some(tid) = entry?.file_name().to_str().and_then(|tid| tid.parse().ok()) {
+            tids.push(tid);
+        }
+    }
+    tids.sort_by_key(|&tid| (tid != pid, tid));
+    Ok(tids)
+}
+
+/// Parse a list of CPUs in the kernel's format, e.g. `0-2,5`
+pub fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = vec![];
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        match range.split_once('-') {
+            Some((start, end)) => {
+                let (start, end): (usize, usize) = (start.parse().ok()?, end.parse().ok()?);
+                if start > end {
+                    return None;
+                }
+                cpus.extend(start..=end);
+            }
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+/// The CPUs that the scheduler treats specially, as `(cpu, reason)`. These are
+/// the CPUs isolated with `isolcpus` and those running with `nohz_full`.
+/// Scheduling on these doesn't reflect normal CFS behaviour.
+///
+/// Older kernels, or those without `CONFIG_NO_HZ_FULL`, don't have the sysfs
+/// files, so we fall back to the kernel command line.
+pub fn special_cpus() -> Vec<(usize, &'static str)> {
+    let cmdline = fs::read_to_string("/proc/cmdline").unwrap_or_default();
+    let mut special = vec![];
+    for (file_name, reason) in [("isolated", "isolcpus"), ("nohz_full", "nohz_full")] {
+        let list = fs::read_to_string(Path::new("/sys/devices/system/cpu").join(file_name))
+            .ok()
+            .or_else(|| {
+                let param = cmdline
+                    .split_whitespace()
+                    .find_map(|param| param.strip_prefix(reason)?.strip_prefix('='))?;
+                // isolcpus may start with flags, e.g. `isolcpus=domain,2-3`
+                let flags = param
+                    .split(',')
+                    .take_while(|flag| flag.starts_with(|c: char| c.is_ascii_alphabetic()));
+                let skip = flags.map(|flag| flag.len() + 1).sum::<usize>();
+                Some(String::from(param.get(skip..).unwrap_or_default()))
+            });
+        let cpus = list
+            .and_then(|list| parse_cpu_list(&list))
+            .unwrap_or_default();
+        special.extend(cpus.into_iter().map(|cpu| (cpu, reason)));
+    }
+    special
+}
+
+/// The CPUs that are currently online, if sysfs says
+pub fn online_cpu_list() -> Option<Vec<usize>> {
+    let list = fs::read_to_string("/sys/devices/system/cpu/online").ok()?;
+    parse_cpu_list(&list)
+}
+
+/// The number of CPUs that are currently online
+pub fn online_cpus() -> usize {
+    // this can only fail for an invalid name
+    let count = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) };
+    count.max(1) as usize
+}
+
+/// The number of NUMA nodes on this machine, according to sysfs. This is 1 if
+/// the kernel was built without NUMA support.
+pub fn numa_node_count() -> usize {
+    let Ok(entries) = fs::read_dir("/sys/devices/system/node") else {
+        return 1;
+    };
+    let count = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.strip_prefix("node")
+                .is_some_and(|id| !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()))
+        })
+        .count();
+    count.max(1)
+}
+
+/// The distinct cpufreq governors that the CPUs are using, according to sysfs,
+/// e.g. `["performance"]`. This is empty if there is no frequency scaling,
+/// which is normal in VMs.
+pub fn cpu_governors() -> Vec<String> {
+    let Ok(entries) = fs::read_dir("/sys/devices/system/cpu") else {
+        return vec![];
+    };
+    let mut governors = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.strip_prefix("cpu")
+                .is_some_and(|id| !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()))
+        })
+        .filter_map(|entry| fs::read_to_string(entry.path().join("cpufreq/scaling_governor")).ok())
+        .map(|governor| String::from(governor.trim()))
+        .collect::<Vec<_>>();
+    governors.sort();
+    governors.dedup();
+    governors
+}
+
+/// What a [`Sched`] field represents, which decides how it is colored in the
+/// tui
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    /// Identifies the process or where it runs, e.g. the nice level
+    Identity,
+    /// A time, in milliseconds
+    Time,
+    /// A counter, weight or average
+    Counter,
+    /// A schedstats field, which is only there when the kernel collects
+    /// schedstats
+    Schedstat,
+    /// Anything else, e.g. the policy
+    Other,
+    /// A field that we couldn't parse
+    Unknown,
+}
+
+impl FieldKind {
+    /// Format a value of this kind to be read at a glance, e.g. a time in
+    /// milliseconds as `1.235s`. Anything that isn't a number is left as it
+    /// is.
+    pub fn humanize(&self, val: String) -> String {
+        match (self, val.parse::<f64>()) {
+            (Self::Time, Ok(ms)) if ms.abs() >= 1000.0 => format!("{:.3}s", ms / 1000.0),
+            (Self::Time, Ok(ms)) => format!("{ms:.3}ms"),
+            _ => val,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
@@ -256,78 +1356,185 @@ impl fmt::Display for SchedCreationError {
             Self::UnexpectedFileFormat => "sched file contained unexpected format",
             Self::GetniceError(err) => match err {
                 GetniceError::Permission => "user lacks permissions to /sched info",
+                GetniceError::InvalidTarget | GetniceError::NoSuchTarget => {
+                    "could not get the nice value of the process"
+                }
             },
         };
         write!(f, "{s}")
     }
 }
 
+/// Quote a CSV field if it needs it, e.g. a wchan with a comma in it
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        String::from(s)
+    }
+}
+
 impl Sched {
-    /// Parse the value of a given key according to the format of /sched.  
-    /// Order matters here.
-    fn parse_val<'a, Val>(
-        k: &'a str,
-    ) -> impl Fn(&'a str) -> nom::IResult<&str, std::result::Result<Val, <Val as FromStr>::Err>>
-    where
-        Val: FromStr,
-    {
-        move |input| {
-            let (input, _k) = take_until(k)(input)?;
-            let (input, _whitespace) = take_until(":")(input)?;
-            let (input, _colon) = tag(":")(input)?;
-            let (input, v_whitespace) = take_while(|ch: char| ch != '\n')(input)?;
-            let valstr = v_whitespace.trim();
-            let val = str::parse::<Val>(valstr);
-            Ok((input, val))
-        }
+    /// Scan a /sched file into a map from each field's key to its value, e.g.
+    /// `"se.vruntime" => "5898.099148"`. Lines that aren't `key : value` are
+    /// skipped, and so the order of the fields doesn't matter. Keys are only
+    /// ever matched whole, so `uclamp.min` can't be mistaken for the end of
+    /// `effective uclamp.min`.
+    fn parse_keys(sched: &str) -> HashMap<&str, &str> {
+        sched
+            .lines()
+            .filter_map(|line| {
+                let (key, val) = line.split_once(':')?;
+                Some((key.trim(), val.trim()))
+            })
+            .collect()
     }
 
     /// Construct a [`Sched`] of the current process
-    #[allow(unused)]
     pub fn this() -> std::result::Result<Self, SchedCreationError> {
-        let this_pid = unistd::Pid::this().as_raw() as i32;
+        let this_pid = unistd::Pid::this().as_raw();
         Self::of(this_pid)
     }
 
     /// Construct a [`Sched`] representing the specified process
     pub fn of(pid: libc::pid_t) -> std::result::Result<Self, SchedCreationError> {
-        let sched = fs::read_to_string(format!("/proc/{pid}/sched"))
-            .map_err(|_| SchedCreationError::FileError)?;
+        Self::of_with(pid, false)
+    }
 
-        macro_rules! map_uff {
-            ($val:expr) => {
-                $val.map_err(|_| SchedCreationError::UnexpectedFileFormat)
-            };
-        }
+    /// Construct a [`Sched`] representing the specified process. Fields that
+    /// this kernel doesn't have are marked as unknown. If `tolerant` is set,
+    /// fields that can't be parsed are too, instead of failing.
+    pub fn of_with(
+        pid: libc::pid_t,
+        tolerant: bool,
+    ) -> std::result::Result<Self, SchedCreationError> {
+        Self::of_task(pid, &Self::read_raw(pid)?, tolerant)
+    }
 
-        macro_rules! parse_val {
-            ($input:expr, $key:expr => $Type:ty) => {{
-                let (input, result) = map_uff!(Self::parse_val($key)($input))?;
-                let val: $Type = map_uff!(result)?;
-                (input, val)
-            }};
-        }
+    /// Construct a [`Sched`] representing thread `tid` of process `pid`. The
+    /// sched of a process is really that of its main thread, so this is how
+    /// to see the threads that do the work in a multithreaded process.
+    pub fn of_thread(
+        pid: libc::pid_t,
+        tid: libc::pid_t,
+    ) -> std::result::Result<Self, SchedCreationError> {
+        Self::of_thread_with(pid, tid, false)
+    }
+
+    /// See [`Sched::of_thread`], and [`Sched::of_with`] for `tolerant`
+    pub fn of_thread_with(
+        pid: libc::pid_t,
+        tid: libc::pid_t,
+        tolerant: bool,
+    ) -> std::result::Result<Self, SchedCreationError> {
+        Self::of_task(tid, &Self::read_raw_thread(pid, tid)?, tolerant)
+    }
+
+    /// Construct a [`Sched`] from the /sched file of the thread `tid`, which
+    /// is also the pid for a whole process. The nice level and so on are per
+    /// thread too.
+    fn of_task(
+        tid: libc::pid_t,
+        sched: &str,
+        tolerant: bool,
+    ) -> std::result::Result<Self, SchedCreationError> {
+        Ok(Self {
+            ni: getnice(tid)?,
+            // this can only fail if the thread has exited, in which case we
+            // would have already failed to read the file
+            reset_on_fork: reset_on_fork(tid).unwrap_or_default(),
+            wchan: wchan(tid).unwrap_or_default(),
+            timer_slack: timer_slack(tid).unwrap_or_default(),
+            ..Self::parse(sched, tolerant)?
+        })
+    }
+
+    /// Read the /sched file of the specified process, without parsing it
+    #[cfg(target_os = "linux")]
+    pub fn read_raw(pid: libc::pid_t) -> std::result::Result<String, SchedCreationError> {
+        fs::read_to_string(format!("/proc/{pid}/sched")).map_err(|_| SchedCreationError::FileError)
+    }
+
+    /// There is no /proc/[pid]/sched outside of Linux, so this always fails
+    #[cfg(not(target_os = "linux"))]
+    pub fn read_raw(_pid: libc::pid_t) -> std::result::Result<String, SchedCreationError> {
+        Err(SchedCreationError::FileError)
+    }
+
+    /// Read the /sched file of thread `tid` of process `pid`, without parsing
+    /// it
+    #[cfg(target_os = "linux")]
+    pub fn read_raw_thread(
+        pid: libc::pid_t,
+        tid: libc::pid_t,
+    ) -> std::result::Result<String, SchedCreationError> {
+        fs::read_to_string(format!("/proc/{pid}/task/{tid}/sched"))
+            .map_err(|_| SchedCreationError::FileError)
+    }
+
+    /// There is no /proc/[pid]/task/[tid]/sched outside of Linux, so this
+    /// always fails
+    #[cfg(not(target_os = "linux"))]
+    pub fn read_raw_thread(
+        _pid: libc::pid_t,
+        _tid: libc::pid_t,
+    ) -> std::result::Result<String, SchedCreationError> {
+        Err(SchedCreationError::FileError)
+    }
+
+    /// Construct a [`Sched`] from a copy of a /sched file that was saved
+    /// earlier, e.g. with `cat /proc/[pid]/sched > baseline`. The fields that
+    /// are not normally in `Sched` are left as their defaults. See
+    /// [`Sched::of_with`] for `tolerant`.
+    pub fn load(path: &Path, tolerant: bool) -> std::result::Result<Self, SchedCreationError> {
+        let sched = fs::read_to_string(path).map_err(|_| SchedCreationError::FileError)?;
+        Self::parse(&sched, tolerant)
+    }
+
+    /// Parse the contents of a /sched file. The fields that are not normally
+    /// in `Sched` are left as their defaults. See [`Sched::of_with`] for
+    /// `tolerant`.
+    pub fn parse(sched: &str, tolerant: bool) -> std::result::Result<Self, SchedCreationError> {
+        let vals = Self::parse_keys(sched);
 
         macro_rules! parse {
             (
-                $input:expr,
                 $(let $ident:ident: $Type:ty = $key:expr);*
                 $(;)?
             ) => {{
-                let input = $input;
+                let mut unknown = vec![];
+                let mut found = false;
                 $(
-                    #[allow(unused)]
-                    let (input, $ident) = parse_val!(input, $key => $Type);
+                    let $ident = match vals.get($key).map(|val| val.parse::<$Type>()) {
+                        Some(Ok(val)) => {
+                            found = true;
+                            val
+                        }
+                        // not every kernel has every field
+                        None => {
+                            unknown.push($key);
+                            <$Type>::default()
+                        }
+                        Some(Err(..)) if tolerant => {
+                            unknown.push($key);
+                            <$Type>::default()
+                        }
+                        Some(Err(..)) => return Err(SchedCreationError::UnexpectedFileFormat),
+                    };
                 )*
+                // any /sched file has at least some of these
+                if !found {
+                    return Err(SchedCreationError::UnexpectedFileFormat);
+                }
                 Self {
                     $($ident),*,
-                    ni: getnice(pid)?,
+                    unknown,
+                    ..Self::default()
                 }
             }};
         }
 
-        Ok(parse! {
-            &sched,
+        let mut parsed = parse! {
             let exec_start: f64 = "se.exec_start";
             let vruntime: f64 = "se.vruntime";
             let sum_exec_runtime: f64 = "se.sum_exec_runtime";
@@ -356,79 +1563,486 @@ impl Sched {
             let numa_pages_migrated: u64 = "numa_pages_migrated";
             let numa_preferred_nid: i64 = "numa_preferred_nid";
             let total_numa_faults: u64 = "total_numa_faults";
-        })
+        };
+        // these are only there when the kernel collects schedstats, under
+        // names that have changed between versions
+        parsed.wait_sum =
+            Self::parse_optional(&vals, &["stats.wait_sum", "se.statistics.wait_sum"])
+                .unwrap_or_default();
+        parsed.wait_count =
+            Self::parse_optional(&vals, &["stats.wait_count", "se.statistics.wait_count"])
+                .unwrap_or_default();
+        parsed.nr_forced_migrations = Self::parse_optional(
+            &vals,
+            &[
+                "stats.nr_forced_migrations",
+                "se.statistics.nr_forced_migrations",
+                "se.nr_forced_migrations",
+            ],
+        )
+        .unwrap_or_default();
+        Ok(parsed)
+    }
+
+    /// Parse a field that not every kernel has, under the first of `keys`
+    /// that is in `vals`. This is `None` if none of them are there, or the
+    /// value can't be parsed.
+    fn parse_optional<Val: FromStr>(vals: &HashMap<&str, &str>, keys: &[&str]) -> Option<Val> {
+        keys.iter().find_map(|key| vals.get(key)?.parse().ok())
     }
 
-    /// Convert this to a [`Paragraph`] widget
-    pub fn as_para(&self, width: usize) -> Paragraph<'static> {
-        fn line(
-            width: usize,
-            field_name: &str,
-            val: impl fmt::Display,
-            color: RatatuiColor,
-        ) -> Line {
-            let val_str = format!("{val}");
-            let min_width = val_str.len() + field_name.len();
-            let whitespace = if min_width < width {
-                width - min_width
-            } else {
-                1
+    /// Every field as `(field_name, value, kind)`, in display order
+    pub fn fields(&self) -> Vec<(&'static str, String, FieldKind)> {
+        macro_rules! fields {
+            ($($field:expr => $val:expr, $kind:ident);* $(;)?) => {
+                vec![$(
+                    if self.unknown.contains(&$field) {
+                        ($field, String::from("—"), FieldKind::Unknown)
+                    } else {
+                        ($field, format!("{}", $val), FieldKind::$kind)
+                    }
+                ),*]
             };
+        }
 
-            Line::from(vec![
-                Span::styled(field_name, Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" ".repeat(whitespace)),
-                Span::styled(val_str, Style::default().fg(color)),
-            ])
+        fields! {
+            "ni" => self.ni, Identity;
+            "se.exec_start" => self.exec_start, Time;
+            "se.vruntime" => self.vruntime, Time;
+            "se.sum_exec_runtime" => self.sum_exec_runtime, Time;
+            "se.nr_migrations" => self.nr_migrations, Counter;
+            "nr_switches" => self.nr_switches, Counter;
+            "nr_voluntary_switches" => self.nr_voluntary_switches, Counter;
+            "nr_involuntary_switches" => self.nr_involuntary_switches, Counter;
+            "stats.wait_sum" => self.wait_sum, Schedstat;
+            "stats.wait_count" => self.wait_count, Schedstat;
+            "stats.nr_forced_migrations" => self.nr_forced_migrations, Schedstat;
+            "se.load.weight" => self.load_weight, Counter;
+            "se.avg.load_sum" => self.avg_load_sum, Counter;
+            "se.avg.runnable_sum" => self.avg_runnable_sum, Counter;
+            "se.avg.util_sum" => self.avg_util_sum, Counter;
+            "se.avg.load_avg" => self.avg_load_avg, Counter;
+            "se.avg.runnable_avg" => self.avg_runnable_avg, Counter;
+            "se.avg.util_avg" => self.avg_util_avg, Counter;
+            "se.avg.last_update_time" => self.avg_last_update_time, Counter;
+            "se.avg.util_est.ewma" => self.avg_util_est_ewma, Counter;
+            "se.avg.util_est.enqueued" => self.avg_util_est_enqueued, Counter;
+            "uclamp.min" => self.uclamp_min, Counter;
+            "uclamp.max" => self.uclamp_max, Counter;
+            "effective uclamp.min" => self.effective_uclamp_min, Counter;
+            "effective uclamp.max" => self.effective_uclamp_max, Counter;
+            "policy" => self.policy, Other;
+            "reset_on_fork" => self.reset_on_fork, Other;
+            "wchan" => self.wchan, Other;
+            "timer_slack_ns" => self.timer_slack, Other;
+            "prio" => self.prio, Counter;
+            "clock-delta" => self.clock_delta, Counter;
+            "mm->numa_scan_seq" => self.numa_scan_seq, Counter;
+            "numa_pages_migrated" => self.numa_pages_migrated, Counter;
+            "numa_preferred_nid" => self.numa_preferred_nid, Identity;
+            "total_numa_faults" => self.total_numa_faults, Counter;
         }
+    }
 
-        macro_rules! line {
-            ($field:expr, $val:expr, $color:ident) => {
-                line(width, $field, $val, RatatuiColor::$color)
-            };
-            ($field:expr, $val:expr) => {
-                line(width, $field, $val, RatatuiColor::default())
-            };
+    /// Every field as `(field_name, value)` pairs, in display order. Anything
+    /// that outputs a [`Sched`] should use this, so that all output formats
+    /// agree on which fields there are.
+    pub fn report_lines(&self) -> Vec<(String, String)> {
+        self.fields()
+            .into_iter()
+            .map(|(field_name, val, _)| (String::from(field_name), val))
+            .collect()
+    }
+
+    /// Format every field as a single JSON object, keyed by the names that
+    /// /sched uses. Fields that couldn't be parsed keep their defaults, and
+    /// are listed in `unknown`.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("a Sched has no maps or fallible fields")
+    }
+
+    /// The header row for [`Sched::to_csv`]
+    pub fn csv_header(&self) -> String {
+        let names = self
+            .report_lines()
+            .into_iter()
+            .map(|(field_name, _)| csv_field(&field_name))
+            .collect::<Vec<_>>();
+        names.join(",")
+    }
+
+    /// Format every field as a single CSV row, in the same order as
+    /// [`Sched::csv_header`]. Fields that couldn't be parsed are empty.
+    pub fn to_csv(&self) -> String {
+        let vals = self
+            .fields()
+            .into_iter()
+            .map(|(_, val, kind)| match kind {
+                FieldKind::Unknown => String::new(),
+                _ => csv_field(&val),
+            })
+            .collect::<Vec<_>>();
+        vals.join(",")
+    }
+
+    /// Compare every field of this with `other`, taking this as the starting
+    /// point
+    pub fn diff(&self, other: &Sched) -> SchedDiff {
+        let fields = self
+            .fields()
+            .into_iter()
+            .zip(other.fields())
+            .map(|((field_name, from, from_kind), (_, to, to_kind))| {
+                let unknown = [from_kind, to_kind].map(|kind| kind == FieldKind::Unknown);
+                let delta = match unknown {
+                    [true, true] => Delta::Same,
+                    [true, false] | [false, true] => Delta::Unknown,
+                    _ if from == to => Delta::Same,
+                    _ => match (from.parse::<i64>(), to.parse::<i64>()) {
+                        (Ok(from), Ok(to)) => Delta::Int(to - from),
+                        _ => match (from.parse::<f64>(), to.parse::<f64>()) {
+                            (Ok(from), Ok(to)) => Delta::Float(to - from),
+                            _ => Delta::Changed,
+                        },
+                    },
+                };
+                FieldDiff {
+                    field_name,
+                    from,
+                    to,
+                    delta,
+                }
+            })
+            .collect();
+        SchedDiff { fields }
+    }
+
+    /// The fields that only mean something on a machine with several NUMA
+    /// nodes
+    pub const NUMA_FIELDS: [&'static str; 4] = [
+        "mm->numa_scan_seq",
+        "numa_pages_migrated",
+        "numa_preferred_nid",
+        "total_numa_faults",
+    ];
+
+    /// Whether a field only means something to CFS, so it matters less on a
+    /// PREEMPT_RT kernel where latency-critical work doesn't run under CFS
+    pub fn is_cfs_field(field_name: &str) -> bool {
+        matches!(field_name, "se.vruntime" | "se.load.weight") || field_name.starts_with("se.avg.")
+    }
+}
+
+/// How much a [`Sched`] field changed by
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Delta {
+    Same,
+    /// An integer field, as `to - from`
+    Int(i64),
+    /// A fractional field (normally a time), as `to - from`
+    Float(f64),
+    /// A field that isn't a number, e.g. the policy, which is different
+    Changed,
+    /// A field that we could only parse on one side
+    Unknown,
+}
+
+impl fmt::Display for Delta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Int(delta) => write!(f, "{delta:+}"),
+            Self::Float(delta) => write!(f, "{delta:+.6}"),
+            Self::Same | Self::Changed | Self::Unknown => Ok(()),
         }
+    }
+}
 
-        Paragraph::new(vec![
-            line!("ni", self.ni, LightBlue),
-            line!("se.exec_start", self.exec_start, Red),
-            line!("se.vruntime", self.vruntime, Red),
-            line!("se.sum_exec_runtime", self.sum_exec_runtime, Red),
-            line!("se.nr_migrations", self.nr_migrations, Green),
-            line!("nr_switches", self.nr_switches, Green),
-            line!("nr_voluntary_switches", self.nr_voluntary_switches, Green),
-            line!(
-                "nr_involuntary_switches",
-                self.nr_involuntary_switches,
-                Green
-            ),
-            line!("se.load.weight", self.load_weight, Green),
-            line!("se.avg.load_sum", self.avg_load_sum, Green),
-            line!("se.avg.runnable_sum", self.avg_runnable_sum, Green),
-            line!("se.avg.util_sum", self.avg_util_sum, Green),
-            line!("se.avg.load_avg", self.avg_load_avg, Green),
-            line!("se.avg.runnable_avg", self.avg_runnable_avg, Green),
-            line!("se.avg.util_avg", self.avg_util_avg, Green),
-            line!("se.avg.last_update_time", self.avg_last_update_time, Green),
-            line!("se.avg.util_est.ewma", self.avg_util_est_ewma, Green),
-            line!(
-                "se.avg.util_est.enqueued",
-                self.avg_util_est_enqueued,
-                Green
-            ),
-            line!("uclamp.min", self.uclamp_min, Green),
-            line!("uclamp.max", self.uclamp_max, Green),
-            line!("effective uclamp.min", self.effective_uclamp_min, Green),
-            line!("effective uclamp.max", self.effective_uclamp_max, Green),
-            line!("policy", self.policy),
-            line!("prio", self.prio, Green),
-            line!("clock-delta", self.clock_delta, Green),
-            line!("mm->numa_scan_seq", self.numa_scan_seq, Green),
-            line!("numa_pages_migrated", self.numa_pages_migrated, Green),
-            line!("numa_preferred_nid", self.numa_preferred_nid, LightBlue),
-            line!("total_numa_faults", self.total_numa_faults, Green),
-        ])
+/// One field of a [`SchedDiff`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDiff {
+    pub field_name: &'static str,
+    pub from: String,
+    pub to: String,
+    pub delta: Delta,
+}
+
+/// How every field differs between two [`Sched`]s, from [`Sched::diff`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchedDiff {
+    fields: Vec<FieldDiff>,
+}
+
+impl SchedDiff {
+    /// Every field, in display order
+    pub fn iter(&self) -> impl Iterator<Item = &FieldDiff> {
+        self.fields.iter()
+    }
+
+    /// The fields that are different, in display order
+    pub fn changed(&self) -> impl Iterator<Item = &FieldDiff> {
+        self.fields
+            .iter()
+            .filter(|field| field.delta != Delta::Same)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sched_json_escapes_strings() {
+        let mut sched = Sched {
+            vruntime: 93.734372,
+            nr_switches: 17,
+            policy: SchedPolicy::RoundRobin,
+            ..Sched::default()
+        };
+        sched.wchan = String::from("a \"quoted\"\\name\n");
+        sched.unknown.push("uclamp.min");
+        let json: serde_json::Value = serde_json::from_str(&sched.to_json()).unwrap();
+        assert_eq!(json["se.vruntime"], 93.734372);
+        assert_eq!(json["nr_switches"], 17);
+        assert_eq!(json["policy"], "SCHED_RR");
+        assert_eq!(json["wchan"], "a \"quoted\"\\name\n");
+        assert_eq!(json["unknown"], serde_json::json!(["uclamp.min"]));
+    }
+
+    /// `/proc/[pid]/sched` of a 5.15 kernel with uclamp and schedstats
+    const SCHED_5: &str = "\
+bash (1234, #threads: 1)
+-------------------------------------------------------------------
+se.exec_start                                :      12345678.901234
+se.vruntime                                  :          5898.099148
+se.sum_exec_runtime                          :            35.345112
+se.nr_migrations                             :                    3
+sum_sleep_runtime                            :         42001.992340
+wait_start                                   :             0.000000
+sleep_start                                  :      12345678.901234
+stats.wait_sum                               :             1.283716
+stats.wait_count                             :                   85
+stats.nr_forced_migrations                   :                    2
+nr_switches                                  :                   84
+nr_voluntary_switches                        :                   80
+nr_involuntary_switches                      :                    4
+se.load.weight                               :              1048576
+se.avg.load_sum                              :                 2376
+se.avg.runnable_sum                          :              2435072
+se.avg.util_sum                              :              2396160
+se.avg.load_avg                              :                   50
+se.avg.runnable_avg                          :                   50
+se.avg.util_avg                              :                   49
+se.avg.last_update_time                      :       12345678901248
+se.avg.util_est.ewma                         :                   38
+se.avg.util_est.enqueued                     :                   49
+uclamp.min                                   :                  128
+uclamp.max                                   :                 1024
+effective uclamp.min                         :                  256
+effective uclamp.max                         :                  512
+policy                                       :                    0
+prio                                         :                  120
+clock-delta                                  :                   40
+mm->numa_scan_seq                            :                    0
+numa_pages_migrated                          :                    0
+numa_preferred_nid                           :                   -1
+total_numa_faults                            :                    0
+current_node=0, numa_group_id=0
+numa_faults node=0 task_private=0 task_shared=0 group_private=0 group_shared=0
+";
+
+    /// `/proc/[pid]/sched` of a 6.18 kernel (EEVDF) without uclamp or
+    /// schedstats, where `se.avg.util_est` is a single field
+    const SCHED_6: &str = "\
+cat (14466, #threads: 1)
+-------------------------------------------------------------------
+se.exec_start                                :       6872928.065818
+se.vruntime                                  :             3.004332
+se.sum_exec_runtime                          :             0.126938
+se.nr_migrations                             :                    0
+nr_switches                                  :                    1
+nr_voluntary_switches                        :                    0
+nr_involuntary_switches                      :                    1
+se.load.weight                               :              1048576
+se.avg.load_sum                              :                47716
+se.avg.runnable_sum                          :             13025072
+se.avg.util_sum                              :             12959536
+se.avg.load_avg                              :                 1024
+se.avg.runnable_avg                          :                  270
+se.avg.util_avg                              :                  270
+se.avg.last_update_time                      :        6872928065536
+se.avg.util_est                              :                    0
+policy                                       :                    0
+prio                                         :                  120
+se.slice                                     :               700000
+clock-delta                                  :                   49
+mm->numa_scan_seq                            :                    0
+numa_pages_migrated                          :                    0
+numa_preferred_nid                           :                   -1
+total_numa_faults                            :                    0
+current_node=0, numa_group_id=0
+numa_faults node=0 task_private=0 task_shared=0 group_private=0 group_shared=0
+";
+
+    #[test]
+    fn parse_keys_5x() {
+        let vals = Sched::parse_keys(SCHED_5);
+        assert_eq!(vals["se.vruntime"], "5898.099148");
+        assert_eq!(vals["mm->numa_scan_seq"], "0");
+        assert_eq!(vals["numa_preferred_nid"], "-1");
+        assert_eq!(vals["stats.wait_count"], "85");
+        // the lines without a `:` aren't fields
+        assert!(!vals.keys().any(|key| key.starts_with("current_node")));
+        assert!(!vals.keys().any(|key| key.starts_with('-')));
+
+        let sched = Sched::parse(SCHED_5, false).unwrap();
+        assert_eq!(sched.vruntime, 5898.099148);
+        assert_eq!(sched.avg_util_est_ewma, 38);
+        assert_eq!(sched.wait_count, 85);
+        assert_eq!(sched.nr_forced_migrations, 2);
+        assert!(sched.unknown.is_empty(), "{:?}", sched.unknown);
+    }
+
+    #[test]
+    fn parse_keys_6x() {
+        let vals = Sched::parse_keys(SCHED_6);
+        assert_eq!(vals["se.avg.util_est"], "0");
+        assert_eq!(vals["se.slice"], "700000");
+
+        let sched = Sched::parse(SCHED_6, false).unwrap();
+        assert_eq!(sched.vruntime, 3.004332);
+        assert_eq!(sched.avg_last_update_time, 6872928065536);
+        assert_eq!(sched.nr_involuntary_switches, 1);
+        // schedstats are off, which isn't unknown
+        assert_eq!(sched.wait_count, 0);
+        for key in [
+            "se.avg.util_est.ewma",
+            "se.avg.util_est.enqueued",
+            "uclamp.min",
+            "uclamp.max",
+            "effective uclamp.min",
+            "effective uclamp.max",
+        ] {
+            assert!(sched.unknown.contains(&key), "{key} should be unknown");
+        }
+        assert_eq!(sched.unknown.len(), 6);
+    }
+
+    #[test]
+    fn parse_keys_uclamp_matches_whole_keys() {
+        let vals = Sched::parse_keys(SCHED_5);
+        assert_eq!(vals["uclamp.min"], "128");
+        assert_eq!(vals["effective uclamp.min"], "256");
+        assert_eq!(vals["uclamp.max"], "1024");
+        assert_eq!(vals["effective uclamp.max"], "512");
+
+        // in either order
+        let reversed = SCHED_5.lines().rev().collect::<Vec<_>>().join("\n");
+        let sched = Sched::parse(&reversed, false).unwrap();
+        assert_eq!(sched.uclamp_min, 128);
+        assert_eq!(sched.effective_uclamp_min, 256);
+        assert_eq!(sched.uclamp_max, 1024);
+        assert_eq!(sched.effective_uclamp_max, 512);
+    }
+
+    #[test]
+    fn parse_keys_odd_spacing() {
+        let vals = Sched::parse_keys(
+            "se.vruntime:1.5\n  nr_switches \t:\t 7  \r\nprio   :120\n\n   \npolicy :  \n",
+        );
+        assert_eq!(vals["se.vruntime"], "1.5");
+        assert_eq!(vals["nr_switches"], "7");
+        assert_eq!(vals["prio"], "120");
+        assert_eq!(vals["policy"], "");
+        assert_eq!(vals.len(), 4);
+    }
+
+    #[test]
+    fn parse_keys_missing_and_extra() {
+        let sched = Sched::parse(
+            "se.vruntime : 2.5\nse.deadline : 1234\nnr_switches : 9\nfuture.field : x\n",
+            false,
+        )
+        .unwrap();
+        assert_eq!(sched.vruntime, 2.5);
+        assert_eq!(sched.nr_switches, 9);
+        assert!(sched.unknown.contains(&"se.exec_start"));
+        assert!(sched.unknown.contains(&"policy"));
+        assert!(!sched.unknown.contains(&"nr_switches"));
+
+        let bad = "se.vruntime : 2.5\nnr_switches : lots\n";
+        assert_eq!(
+            Sched::parse(bad, false).unwrap_err(),
+            SchedCreationError::UnexpectedFileFormat
+        );
+        let tolerated = Sched::parse(bad, true).unwrap();
+        assert!(tolerated.unknown.contains(&"nr_switches"));
+
+        // nothing that we know of at all
+        assert_eq!(
+            Sched::parse("future.field : x\n", false).unwrap_err(),
+            SchedCreationError::UnexpectedFileFormat
+        );
+        assert_eq!(
+            Sched::parse("", true).unwrap_err(),
+            SchedCreationError::UnexpectedFileFormat
+        );
+    }
+
+    #[test]
+    fn sched_diff() {
+        let from = Sched::parse(SCHED_5, false).unwrap();
+        let mut to = from.clone();
+        to.vruntime -= 0.5;
+        to.nr_switches += 16;
+        to.numa_preferred_nid = -3;
+        to.policy = SchedPolicy::Batch;
+        to.unknown.push("uclamp.min");
+
+        let diff = from.diff(&to);
+        assert_eq!(diff.iter().count(), from.fields().len());
+        let changed = diff
+            .changed()
+            .map(|field| (field.field_name, field.delta))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changed,
+            [
+                ("se.vruntime", Delta::Float(-0.5)),
+                ("nr_switches", Delta::Int(16)),
+                ("uclamp.min", Delta::Unknown),
+                ("policy", Delta::Changed),
+                ("numa_preferred_nid", Delta::Int(-2)),
+            ]
+        );
+        let policy = diff
+            .iter()
+            .find(|field| field.field_name == "policy")
+            .unwrap();
+        assert_eq!(
+            (&policy.from[..], &policy.to[..]),
+            ("SCHED_OTHER", "SCHED_BATCH")
+        );
+        assert_eq!(Delta::Int(-2).to_string(), "-2");
+        assert_eq!(Delta::Float(0.25).to_string(), "+0.250000");
+
+        // unknown on both sides, or the same policy, isn't a change
+        let mut same = from.clone();
+        same.unknown.push("uclamp.max");
+        let mut other = same.clone();
+        other.uclamp_max = 7;
+        assert_eq!(same.diff(&other).changed().count(), 0);
+        assert_eq!(from.diff(&from).changed().count(), 0);
+    }
+
+    #[test]
+    fn getnice_of_our_user_and_process_group() {
+        let this = getnice(std::process::id() as i32).unwrap();
+        // we are one of the processes, so the lowest is at most ours
+        let uid = unsafe { libc::getuid() };
+        assert!(getnice_user(uid).unwrap() <= this);
+        let pgid = unsafe { libc::getpgrp() };
+        assert!(getnice_pgid(pgid).unwrap() <= this);
+        // no process can belong to this user
+        assert_eq!(getnice_user(u32::MAX - 1), Err(GetniceError::NoSuchTarget));
     }
 }
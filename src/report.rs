@@ -0,0 +1,141 @@
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    process, thread,
+    time::Duration,
+};
+
+use crate::nix_ext::{self as nix, Sched, SchedCreationError};
+
+/// How often a flood process sends a report about itself
+pub const REPORT_INTERVAL: Duration = Duration::from_millis(200);
+
+/// What a flood process tells the tui about itself, so that the tui doesn't
+/// have to read /proc for it
+///
+/// # Encoding
+/// Each report is a frame of
+/// - the length of the rest of the frame, as a little-endian `u32`
+/// - the nice level, as a little-endian `i32`
+/// - whether `SCHED_RESET_ON_FORK` is set, as a `u8`
+/// - the unparsed /sched file, as utf8
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub ni: i32,
+    pub reset_on_fork: bool,
+    pub sched: String,
+}
+
+impl Report {
+    /// The size of the length prefix of a frame
+    const LEN_SIZE: usize = 4;
+    /// The size of the nice level and flags at the start of a frame
+    const HEADER_SIZE: usize = 5;
+
+    /// Gather a report about the calling process
+    pub fn this() -> Result<Self, SchedCreationError> {
+        let pid = process::id() as i32;
+        Ok(Self {
+            ni: nix::getnice(pid)?,
+            reset_on_fork: nix::reset_on_fork(0).unwrap_or_default(),
+            sched: Sched::read_raw(pid)?,
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let len = Self::HEADER_SIZE + self.sched.len();
+        let mut frame = Vec::with_capacity(Self::LEN_SIZE + len);
+        frame.extend((len as u32).to_le_bytes());
+        frame.extend(self.ni.to_le_bytes());
+        frame.push(self.reset_on_fork as u8);
+        frame.extend(self.sched.as_bytes());
+        frame
+    }
+
+    /// Decode the frame at the start of `buf`, returning it along with its
+    /// size. Returns `None` if the frame is incomplete.
+    fn decode(buf: &[u8]) -> Option<(Option<Self>, usize)> {
+        let len = u32::from_le_bytes(buf.get(..Self::LEN_SIZE)?.try_into().ok()?) as usize;
+        let frame = buf.get(Self::LEN_SIZE..Self::LEN_SIZE + len)?;
+        let report = (len >= Self::HEADER_SIZE)
+            .then(|| {
+                Some(Self {
+                    ni: i32::from_le_bytes(frame[..4].try_into().ok()?),
+                    reset_on_fork: frame[4] != 0,
+                    sched: String::from_utf8(frame[Self::HEADER_SIZE..].to_vec()).ok()?,
+                })
+            })
+            .flatten();
+        Some((report, Self::LEN_SIZE + len))
+    }
+
+    /// Convert this to a [`Sched`] for the process `pid` that sent it. See
+    /// [`Sched::of_with`] for `tolerant`.
+    pub fn to_sched(&self, pid: i32, tolerant: bool) -> Result<Sched, SchedCreationError> {
+        let mut sched = Sched::parse(&self.sched, tolerant)?;
+        sched.ni = self.ni;
+        sched.reset_on_fork = self.reset_on_fork;
+        // the process can't usefully read its own wait channel, since it is
+        // always running when it does
+        sched.wchan = nix::wchan(pid).unwrap_or_default();
+        sched.timer_slack = nix::timer_slack(pid).unwrap_or_default();
+        Ok(sched)
+    }
+}
+
+/// Send a report about the calling process to `file` every
+/// [`REPORT_INTERVAL`], until the reader goes away or we are interrupted
+pub fn send_reports(mut file: File) {
+    while !nix::interrupted() {
+        let Ok(report) = Report::this() else {
+            return;
+        };
+        if file.write_all(&report.encode()).is_err() {
+            return;
+        }
+        thread::sleep(REPORT_INTERVAL);
+    }
+}
+
+/// Receives the reports sent by [`send_reports`]
+pub struct ReportReader {
+    file: File,
+    buf: Vec<u8>,
+    latest: Option<Report>,
+}
+
+impl ReportReader {
+    /// Read reports from `file`, which must not block
+    pub fn new(file: File) -> Self {
+        Self {
+            file,
+            buf: vec![],
+            latest: None,
+        }
+    }
+
+    /// Read any reports that have arrived, and return the latest one we have
+    /// received
+    pub fn latest(&mut self) -> Option<&Report> {
+        let mut chunk = [0; 4096];
+        loop {
+            match self.file.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => self.buf.extend(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                // WouldBlock means that we have read everything for now
+                Err(..) => break,
+            }
+        }
+
+        let mut start = 0;
+        while let Some((report, size)) = Report::decode(&self.buf[start..]) {
+            if report.is_some() {
+                self.latest = report;
+            }
+            start += size;
+        }
+        self.buf.drain(..start);
+        self.latest.as_ref()
+    }
+}
@@ -0,0 +1,163 @@
+use std::env;
+
+use clap::ValueEnum;
+use nice_level::nix_ext::FieldKind;
+use ratatui::style::Color;
+
+/// The themes that can be picked with `--theme` or `$TESTNICE_THEME`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum ThemeName {
+    /// Bright colors for a dark terminal
+    #[default]
+    Dark,
+    /// Darker text colors for a light terminal
+    Light,
+    /// The Okabe-Ito palette, which can be told apart with any kind of color
+    /// blindness, and no red against green
+    Colorblind,
+}
+
+impl ThemeName {
+    /// The environment variable that picks a theme when `--theme` isn't given
+    const ENV_VAR: &'static str = "TESTNICE_THEME";
+
+    /// `name` if it was given, or else the theme named by `$TESTNICE_THEME`,
+    /// or else the default
+    pub(crate) fn or_from_env(name: Option<Self>) -> Result<Self, String> {
+        if let Some(name) = name {
+            return Ok(name);
+        }
+        let Some(val) = env::var_os(Self::ENV_VAR) else {
+            return Ok(Self::default());
+        };
+        let val = val.to_string_lossy();
+        Self::from_str(&val, true).map_err(|_| {
+            let names = Self::value_variants()
+                .iter()
+                .filter_map(|name| Some(String::from(name.to_possible_value()?.get_name())))
+                .collect::<Vec<_>>();
+            format!(
+                "${}: unknown theme `{val}`, expected one of {}",
+                Self::ENV_VAR,
+                names.join(", ")
+            )
+        })
+    }
+
+    pub(crate) const fn theme(self) -> Theme {
+        match self {
+            Self::Dark => Theme::DARK,
+            Self::Light => Theme::LIGHT,
+            Self::Colorblind => Theme::COLORBLIND,
+        }
+    }
+}
+
+/// The colors that the tui tells things apart with. Everything else, like the
+/// status line, has the same colors in every theme.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Theme {
+    /// The colors used to tell the processes apart, in order. These are
+    /// reused if there are more processes than colors. They are drawn behind
+    /// black text.
+    processes: [Color; 6],
+    /// The colors of the kinds of sched field
+    identity: Color,
+    time: Color,
+    counter: Color,
+    schedstat: Color,
+    /// The colors of a field that is close to the baseline, further off, and
+    /// far off
+    pub deviation: [Color; 3],
+    /// The colors of the process that has had more CPU time, and of the one
+    /// that has had less
+    pub more: Color,
+    pub less: Color,
+}
+
+impl Theme {
+    const DARK: Self = Self {
+        processes: [
+            Color::Rgb(255, 0, 255),
+            Color::Yellow,
+            Color::Cyan,
+            Color::LightGreen,
+            Color::LightRed,
+            Color::LightBlue,
+        ],
+        identity: Color::LightBlue,
+        time: Color::Red,
+        counter: Color::Green,
+        schedstat: Color::Magenta,
+        deviation: [Color::Green, Color::Yellow, Color::Red],
+        more: Color::Green,
+        less: Color::Red,
+    };
+
+    const LIGHT: Self = Self {
+        processes: [
+            Color::Rgb(255, 0, 255),
+            Color::Rgb(230, 180, 0),
+            Color::Rgb(0, 190, 210),
+            Color::Rgb(80, 200, 80),
+            Color::Rgb(255, 110, 110),
+            Color::Rgb(110, 150, 255),
+        ],
+        identity: Color::Blue,
+        time: Color::Rgb(190, 0, 0),
+        counter: Color::Rgb(0, 130, 0),
+        schedstat: Color::Rgb(150, 0, 150),
+        deviation: [
+            Color::Rgb(0, 130, 0),
+            Color::Rgb(170, 110, 0),
+            Color::Rgb(190, 0, 0),
+        ],
+        more: Color::Rgb(0, 130, 0),
+        less: Color::Rgb(190, 0, 0),
+    };
+
+    const COLORBLIND: Self = {
+        // https://jfly.uni-koeln.de/color/
+        const ORANGE: Color = Color::Rgb(230, 159, 0);
+        const SKY_BLUE: Color = Color::Rgb(86, 180, 233);
+        const BLUISH_GREEN: Color = Color::Rgb(0, 158, 115);
+        const YELLOW: Color = Color::Rgb(240, 228, 66);
+        const VERMILLION: Color = Color::Rgb(213, 94, 0);
+        const REDDISH_PURPLE: Color = Color::Rgb(204, 121, 167);
+        Self {
+            processes: [
+                ORANGE,
+                SKY_BLUE,
+                YELLOW,
+                REDDISH_PURPLE,
+                BLUISH_GREEN,
+                VERMILLION,
+            ],
+            identity: SKY_BLUE,
+            time: VERMILLION,
+            counter: BLUISH_GREEN,
+            schedstat: REDDISH_PURPLE,
+            deviation: [SKY_BLUE, YELLOW, VERMILLION],
+            more: SKY_BLUE,
+            less: ORANGE,
+        }
+    };
+
+    /// The color of a field of this kind, when it isn't being compared
+    /// against a baseline
+    pub(crate) const fn field(&self, kind: FieldKind) -> Color {
+        match kind {
+            FieldKind::Identity => self.identity,
+            FieldKind::Time => self.time,
+            FieldKind::Counter => self.counter,
+            FieldKind::Schedstat => self.schedstat,
+            FieldKind::Other => Color::Reset,
+            FieldKind::Unknown => Color::DarkGray,
+        }
+    }
+
+    /// The color of the `i`th process
+    pub(crate) fn process(&self, i: usize) -> Color {
+        self.processes[i % self.processes.len()]
+    }
+}
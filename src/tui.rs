@@ -1,24 +1,45 @@
 use nix::libc::{kill, SIGTERM};
 use ratatui::{
     backend::CrosstermBackend,
-    layout::Margin,
-    style::{Color, Style},
+    buffer::Buffer,
+    layout::{Margin, Rect},
+    style::{Color, Modifier, Style},
+    symbols::Marker,
     text::{Line, Span},
-    widgets::{Block, Borders, Paragraph},
+    widgets::{
+        Axis, Block, Borders, Chart, Dataset, GraphType, Paragraph, Sparkline, Widget, Wrap,
+    },
 };
 use std::{
-    collections::VecDeque,
-    io::{self, Stderr},
-    time::{Duration, Instant},
+    cmp,
+    collections::{HashMap, VecDeque},
+    error::Error,
+    fmt,
+    fs::{self, File},
+    io::{self, IsTerminal, Stderr, Write},
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, Instant, SystemTime},
 };
 
 use crate::{
-    log::{Log, LogEntry, LogError},
-    nix_ext::{Sched, SchedCreationError},
+    cli::FloodCommand,
+    format_note,
+    log::{self, Log, LogEntry, LogError},
+    nix_ext::{
+        self, AffinityError, Delta, FieldDiff, FieldKind, Sched, SchedCreationError, SchedPolicy,
+    },
+    report::ReportReader,
+    theme::Theme,
+    util,
 };
 
 type Terminal = ratatui::Terminal<CrosstermBackend<Stderr>>;
 
+/// A `(pid, color, points)` series in a chart
+type ChartSeries<'a> = (i32, Color, &'a [(f64, f64)]);
+
 struct PeriodicallyUpdate<T> {
     pub val: T,
     pub freq: Duration,
@@ -50,14 +71,834 @@ impl<T> PeriodicallyUpdate<T> {
     }
 }
 
+/// Completions per interval for each process, oldest first
+#[derive(Default)]
+struct Throughput {
+    /// Where we stopped counting in each logfile last interval
+    log_offsets: Vec<u64>,
+    history: HashMap<i32, VecDeque<u64>>,
+    /// When we last saw each process complete a task (or when we started
+    /// watching it, if it hasn't yet)
+    last_progress: HashMap<i32, Instant>,
+    /// How many tasks each thread has completed in total
+    by_tid: HashMap<i32, usize>,
+}
+
+impl Throughput {
+    /// Push the completion counts for the latest interval, dropping the oldest
+    /// interval once we have more than `len`
+    fn record(&mut self, counts: &HashMap<i32, usize>, pids: &[i32], len: usize, now: Instant) {
+        for pid in pids {
+            let count = counts.get(pid).copied().unwrap_or(0);
+            if count > 0 || !self.last_progress.contains_key(pid) {
+                self.last_progress.insert(*pid, now);
+            }
+
+            let history = self.history.entry(*pid).or_default();
+            history.push_back(count as u64);
+            if history.len() > len {
+                history.pop_front();
+            }
+        }
+    }
+
+    /// How long it has been since a process last completed a task, if it has
+    /// been longer than `threshold`
+    fn stalled_for(&self, pid: i32, now: Instant, threshold: Duration) -> Option<Duration> {
+        let stalled_for = now.duration_since(*self.last_progress.get(&pid)?);
+        (stalled_for > threshold).then_some(stalled_for)
+    }
+
+    /// Chart points for a process, where x is the number of seconds before now
+    fn points(&self, pid: i32, interval: Duration) -> Vec<(f64, f64)> {
+        let Some(history) = self.history.get(&pid) else {
+            return vec![];
+        };
+        let newest = history.len() as f64 - 1.0;
+        history
+            .iter()
+            .enumerate()
+            .map(|(i, &count)| ((i as f64 - newest) * interval.as_secs_f64(), count as f64))
+            .collect()
+    }
+}
+
+/// The thread of a process that a pane shows, instead of the whole process
+#[derive(Debug, Clone, Copy)]
+struct ShownThread {
+    tid: i32,
+    /// Where the thread is in the process's threads, and how many it has
+    index: usize,
+    count: usize,
+    /// How many tasks the thread has completed since we started
+    completions: usize,
+}
+
+/// Where the values go in a [`SchedPane`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Align {
+    /// Every value is pushed up against the right edge
+    #[default]
+    Ragged,
+    /// Every value starts in the same column, just after the longest field
+    /// name
+    Column,
+}
+
+/// Shorten `s` to at most `width` characters, ending it with an ellipsis if
+/// anything had to be cut off
+fn truncate(s: &str, width: usize) -> String {
+    if s.chars().count() <= width {
+        return String::from(s);
+    }
+    let mut truncated = s.chars().take(width.saturating_sub(1)).collect::<String>();
+    if width > 0 {
+        truncated.push('…');
+    }
+    truncated
+}
+
+/// Lay out one sched field in `width` columns, with the value starting at
+/// column `val_col` if there is room for it
+fn field_line(
+    width: usize,
+    val_col: usize,
+    field_name: &str,
+    val_str: String,
+    color: Color,
+    dim: bool,
+) -> Line<'static> {
+    // the value is more important than the field name, so the field name is
+    // the first thing to be cut short. We always leave at least one space
+    // between the two.
+    let val_str = truncate(&val_str, width);
+    let val_width = val_str.chars().count();
+    let val_col = val_col.min(width - val_width);
+    let field_name = truncate(field_name, val_col.saturating_sub(1));
+    let whitespace = val_col - field_name.chars().count();
+
+    let name_style = if dim {
+        Style::default().add_modifier(Modifier::DIM)
+    } else {
+        Style::default().add_modifier(Modifier::BOLD)
+    };
+    let val_style = match dim {
+        true => Style::default().fg(color).add_modifier(Modifier::DIM),
+        false => Style::default().fg(color),
+    };
+    Line::from(vec![
+        Span::styled(field_name, name_style),
+        Span::raw(" ".repeat(whitespace)),
+        Span::styled(val_str, val_style),
+    ])
+}
+
+/// The pane for a single process: its sched fields in a box titled with its
+/// pid, and a sparkline of its throughput at the bottom if there is room
+struct SchedPane<'a> {
+    pid: i32,
+    /// The thread that the fields are for, if not the whole process
+    thread: Option<ShownThread>,
+    color: Color,
+    /// `None` once the process has exited
+    sched: Option<&'a Sched>,
+    /// The unparsed /sched file, if we are showing that instead of the parsed
+    /// fields
+    raw_sched: Option<&'a str>,
+    is_watched: bool,
+    /// How long the process has gone without completing a task, if that is
+    /// long enough to warn about
+    stalled_for: Option<Duration>,
+    /// Completions per interval, oldest first, and the count at the top of
+    /// the sparkline's scale. There is no sparkline without this.
+    throughput: Option<(Vec<u64>, u64)>,
+    show_numa: bool,
+    align: Align,
+    /// What the process is compared against, if anything
+    baseline: Option<&'a Sched>,
+    preempt_rt: bool,
+    /// Whether to show the values exactly as /sched has them
+    raw_values: bool,
+    theme: &'a Theme,
+}
+
+impl SchedPane<'_> {
+    /// The height of the sparkline at the bottom of the pane, including its
+    /// title
+    const SPARKLINE_HEIGHT: u16 = 4;
+    /// How much of the pane has to be left over for the fields before we give
+    /// up some of it to the sparkline
+    const MIN_FIELDS_HEIGHT: u16 = 12;
+    /// The kinds of field that have their own color, and what to call them in
+    /// the legend
+    const LEGEND: [(FieldKind, &'static str); 4] = [
+        (FieldKind::Time, "time"),
+        (FieldKind::Counter, "counter"),
+        (FieldKind::Identity, "identity"),
+        (FieldKind::Schedstat, "schedstat"),
+    ];
+    /// How far a value can be from the baseline, as a fraction of the
+    /// baseline, before it is shown as a small deviation
+    const SMALL_DEVIATION: f64 = 0.1;
+    /// How far a value can be from the baseline, as a fraction of the
+    /// baseline, before it is shown as a big deviation
+    const BIG_DEVIATION: f64 = 0.5;
+
+    /// The color for a field that differs from the baseline by `field`.
+    /// Non-numeric fields are either the same or a big deviation.
+    fn deviation_color(&self, field: &FieldDiff) -> Color {
+        let relative = |delta: f64| match field.from.parse::<f64>() {
+            Ok(baseline) => (delta / baseline).abs(),
+            Err(..) => f64::INFINITY,
+        };
+        let deviation = match field.delta {
+            Delta::Same => 0.0,
+            Delta::Int(delta) => relative(delta as f64),
+            Delta::Float(delta) => relative(delta),
+            Delta::Changed | Delta::Unknown => f64::INFINITY,
+        };
+        let [close, off, far] = self.theme.deviation;
+        if deviation <= Self::SMALL_DEVIATION {
+            close
+        } else if deviation <= Self::BIG_DEVIATION {
+            off
+        } else {
+            far
+        }
+    }
+
+    /// The fields of `sched`, laid out in `width` columns. The NUMA fields
+    /// are left out unless `show_numa` is set. If there is a `baseline`, each
+    /// value is colored by how far it is from the baseline, rather than by its
+    /// kind. Otherwise, the first line is a legend for the colors. Times are
+    /// given units unless `raw_values` is set, which shows every value exactly
+    /// as /sched has it, with no legend.
+    fn fields_para(&self, sched: &Sched, width: usize) -> Paragraph<'static> {
+        let fields = sched
+            .fields()
+            .into_iter()
+            .filter(|(field_name, _, _)| self.show_numa || !Sched::NUMA_FIELDS.contains(field_name))
+            .collect::<Vec<_>>();
+        let val_col = match self.align {
+            // `field_line` pulls this back so that each value ends at the edge
+            Align::Ragged => width,
+            Align::Column => {
+                let name_width = fields
+                    .iter()
+                    .map(|(field_name, _, _)| field_name.chars().count());
+                name_width.max().unwrap_or(0) + 1
+            }
+        };
+
+        let baseline_diff = self.baseline.map(|baseline| baseline.diff(sched));
+        let mut lines = vec![];
+        if !self.raw_values && self.baseline.is_none() {
+            let mut legend = vec![];
+            for (kind, name) in Self::LEGEND {
+                legend.push(Span::styled(
+                    "■ ",
+                    Style::default().fg(self.theme.field(kind)),
+                ));
+                legend.push(Span::styled(
+                    format!("{name} "),
+                    Style::default().add_modifier(Modifier::DIM),
+                ));
+            }
+            lines.push(Line::from(legend));
+        }
+        lines.extend(fields.into_iter().map(|(field_name, val_str, kind)| {
+            let baseline_field = baseline_diff
+                .iter()
+                .find_map(|diff| diff.iter().find(|field| field.field_name == field_name));
+            let color = match baseline_field {
+                Some(field) if kind != FieldKind::Unknown => self.deviation_color(field),
+                _ => self.theme.field(kind),
+            };
+            let dim = self.preempt_rt && Sched::is_cfs_field(field_name);
+            let val_str = match self.raw_values {
+                true => val_str,
+                false => kind.humanize(val_str),
+            };
+            field_line(width, val_col, field_name, val_str, color, dim)
+        }));
+        Paragraph::new(lines)
+    }
+
+    fn title(&self) -> Line<'static> {
+        let pid = self.pid;
+        let content = match (self.sched, self.is_watched) {
+            (None, _) => format!("Proc-{pid} (exited) "),
+            (Some(_), true) => format!("Proc-{pid} (watched) "),
+            (Some(_), false) => format!("Proc-{pid} "),
+        };
+        let mut title = vec![Span::styled(content, Style::default().fg(self.color))];
+        if let Some(thread) = self.thread {
+            title.push(Span::styled(
+                format!(
+                    "tid {} ({}/{}, {} done) ",
+                    thread.tid,
+                    thread.index + 1,
+                    thread.count,
+                    thread.completions
+                ),
+                Style::default().fg(Color::Black).bg(self.color),
+            ));
+        }
+        if let Some(sched) = self.sched {
+            title.push(Tui::fmt_policy(sched.policy));
+            // the nice level it actually has, which is not the one we asked
+            // for with --best-effort
+            title.push(Span::raw(format!(" ni {}", sched.ni)));
+        }
+        if let Some(stalled_for) = self.stalled_for {
+            title.push(Span::styled(
+                format!(" no progress for {}s", stalled_for.as_secs()),
+                Style::default().fg(Color::Black).bg(Color::Red),
+            ));
+        }
+        Line::from(title)
+    }
+}
+
+impl Widget for SchedPane<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        Block::default()
+            .borders(Borders::all())
+            .title(self.title())
+            .render(area, buf);
+
+        let mut para_rect = area.inner(&Margin::new(1, 1));
+        if let Some((history, max)) = &self.throughput {
+            if para_rect.height >= Self::MIN_FIELDS_HEIGHT + Self::SPARKLINE_HEIGHT {
+                para_rect.height -= Self::SPARKLINE_HEIGHT;
+                let sparkline_rect = Rect {
+                    y: para_rect.y + para_rect.height,
+                    height: Self::SPARKLINE_HEIGHT,
+                    ..para_rect
+                };
+                // the newest intervals that fit, scrolling in from the right
+                let width = sparkline_rect.width as usize;
+                let mut data = history
+                    .iter()
+                    .rev()
+                    .take(width)
+                    .copied()
+                    .collect::<Vec<_>>();
+                data.resize(width, 0);
+                data.reverse();
+                Sparkline::default()
+                    .block(
+                        Block::default()
+                            .borders(Borders::TOP)
+                            .title("completions/s")
+                            .style(Style::default().fg(Color::DarkGray)),
+                    )
+                    .data(&data)
+                    .max(*max)
+                    .style(Style::default().fg(self.color))
+                    .render(sparkline_rect, buf);
+            }
+        }
+
+        let para = match (self.sched, self.raw_sched) {
+            (None, _) => {
+                Paragraph::new("process exited").style(Style::default().fg(Color::DarkGray))
+            }
+            // the raw lines are usually wider than the pane, and the values
+            // are at the end
+            (Some(_), Some(raw_sched)) => Paragraph::new(raw_sched).wrap(Wrap { trim: false }),
+            (Some(sched), None) => self.fields_para(sched, (area.width as usize).saturating_sub(2)),
+        };
+        para.render(para_rect, buf);
+    }
+}
+
+/// A process shown in the TUI. This is either a flood process that the TUI
+/// spawned, or an existing process that we only watch and must not kill.
+pub struct Worker {
+    pid: i32,
+    /// The command used to spawn the process, so that it can be spawned
+    /// again, if we spawned it
+    spawned: Option<(FloodCommand, process::Child)>,
+    /// The worker's own logfile, if it isn't using the shared one
+    log: Option<Log>,
+    /// The reports that the process sends us, if we spawned it
+    reports: Option<ReportReader>,
+}
+
+impl Worker {
+    /// Spawn `command` as a new process, using the testnice executable at `this`
+    pub fn spawn(command: FloodCommand, this: &PathBuf) -> Result<Self, String> {
+        let (reports, report_fd) =
+            nix_ext::inherited_pipe().map_err(|e| format!("while creating report pipe: {e}"))?;
+        let child = FloodCommand {
+            report_fd: Some(report_fd.as_raw_fd()),
+            ..command.clone()
+        }
+        .spawn_process(this);
+        // only the child should hold the write end, so that we notice if it
+        // goes away
+        drop(report_fd);
+        let child = child?;
+        let pid = child.id() as _;
+        let per_process_log = command.per_process_log.then(|| command.logfile.clone());
+        let mut worker = Self {
+            pid,
+            spawned: Some((command, child)),
+            log: None,
+            reports: Some(ReportReader::new(reports)),
+        };
+        // the shard is named by the pid, so it can only be opened now
+        if let Some(logfile) = per_process_log {
+            match Log::open_shard(&logfile, pid) {
+                Ok(log) => worker.log = Some(log),
+                Err(e) => {
+                    worker.stop();
+                    return Err(format!("{e}"));
+                }
+            }
+        }
+        Ok(worker)
+    }
+
+    /// Watch an existing process, which we don't own
+    pub fn watch(pid: i32) -> Self {
+        Self {
+            pid,
+            spawned: None,
+            log: None,
+            reports: None,
+        }
+    }
+
+    fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    fn is_watched(&self) -> bool {
+        self.spawned.is_none()
+    }
+
+    /// Whether the process has exited. A process that we spawned has been
+    /// reaped once this is true, so its pid may already belong to another one.
+    fn has_exited(&mut self) -> bool {
+        match &mut self.spawned {
+            Some((_, child)) => !matches!(child.try_wait(), Ok(None)),
+            None => !Path::new(&format!("/proc/{}", self.pid)).exists(),
+        }
+    }
+
+    /// Get the process's sched from its latest report, or from /proc if it
+    /// hasn't sent one (or we are only watching it). This is `None` once the
+    /// process has exited.
+    fn sched(&mut self, tolerant: bool) -> Result<Option<Sched>, SchedCreationError> {
+        // the last report is still there after the process has gone
+        if self.has_exited() {
+            return Ok(None);
+        }
+        let sched = match self.reports.as_mut().and_then(ReportReader::latest) {
+            Some(report) => report.to_sched(self.pid, tolerant),
+            None => Sched::of_with(self.pid, tolerant),
+        };
+        match sched {
+            // it may have exited since we checked
+            Err(..) if self.has_exited() => Ok(None),
+            sched => sched.map(Some),
+        }
+    }
+
+    /// Get the sched of thread `tid` of the process from /proc, since reports
+    /// only cover the whole process. This is `None` once the process has
+    /// exited.
+    fn thread_sched(
+        &mut self,
+        tid: i32,
+        tolerant: bool,
+    ) -> Result<Option<Sched>, SchedCreationError> {
+        match Sched::of_thread_with(self.pid, tid, tolerant) {
+            // the thread may have exited with the rest of the process
+            Err(..) if self.has_exited() => Ok(None),
+            sched => sched.map(Some),
+        }
+    }
+
+    /// Pick the `index`th thread of the process, wrapping around if it has
+    /// fewer threads. This is `None` once the process has exited.
+    fn thread(&mut self, index: usize) -> Option<(i32, usize, usize)> {
+        let tids = nix_ext::thread_ids(self.pid)
+            .ok()
+            .filter(|tids| !tids.is_empty())?;
+        let index = index % tids.len();
+        Some((tids[index], index, tids.len()))
+    }
+
+    /// Terminate the process and wait for it to exit, if we spawned it
+    pub fn stop(&mut self) {
+        let Some((_, child)) = &mut self.spawned else {
+            return;
+        };
+        // its pid could belong to something else once it has been reaped
+        if let Ok(None) = child.try_wait() {
+            _ = unsafe { kill(self.pid, SIGTERM) };
+        }
+        _ = child.wait();
+    }
+}
+
+/// Where the tui gets its keypresses from
+pub trait KeySource {
+    /// Wait up to `timeout` for the next keypress
+    fn next_key(&mut self, timeout: Duration) -> io::Result<Option<char>>;
+}
+
+/// The keys that scrolling back and forward through the short-log are read
+/// as, whether by the mouse wheel, PgUp/PgDn or these keys themselves, so
+/// that scrolling can be recorded and replayed like any other key
+const SCROLL_BACK_KEY: char = '<';
+const SCROLL_FORWARD_KEY: char = '>';
+
+/// Wait up to `timeout` for a key to be pressed in the terminal
+fn read_terminal_key(timeout: Duration) -> io::Result<Option<char>> {
+    use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, MouseEvent, MouseEventKind};
+
+    if !crossterm::event::poll(timeout)? {
+        return Ok(None);
+    }
+    let key = match crossterm::event::read()? {
+        Event::Key(KeyEvent {
+            code,
+            kind: KeyEventKind::Press,
+            ..
+        }) => match code {
+            KeyCode::Char(key) => key,
+            KeyCode::PageUp => SCROLL_BACK_KEY,
+            KeyCode::PageDown => SCROLL_FORWARD_KEY,
+            _ => return Ok(None),
+        },
+        Event::Mouse(MouseEvent { kind, .. }) => match kind {
+            MouseEventKind::ScrollUp => SCROLL_BACK_KEY,
+            MouseEventKind::ScrollDown => SCROLL_FORWARD_KEY,
+            _ => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(key))
+}
+
+/// Keypresses from the terminal, optionally recorded to a file so that they
+/// can be replayed with [`ReplayedKeys`]. Each line of the recording is the
+/// number of milliseconds since the tui started, a space and then the key.
+pub struct TerminalKeys {
+    recording: Option<(File, Instant)>,
+}
+
+impl TerminalKeys {
+    pub fn new() -> Self {
+        Self { recording: None }
+    }
+
+    /// Record every keypress to `path`, replacing whatever was there
+    pub fn recording_to(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            recording: Some((File::create(path)?, Instant::now())),
+        })
+    }
+}
+
+impl KeySource for TerminalKeys {
+    fn next_key(&mut self, timeout: Duration) -> io::Result<Option<char>> {
+        let key = read_terminal_key(timeout)?;
+        if let (Some(key), Some((file, started))) = (key, &mut self.recording) {
+            writeln!(file, "{} {key}", started.elapsed().as_millis())?;
+        }
+        Ok(key)
+    }
+}
+
+/// Keypresses from a recording made by [`TerminalKeys`], pressed at the same
+/// times as they were recorded. Keys pressed in the terminal are still handled,
+/// so that a replay can be quit early.
+pub struct ReplayedKeys {
+    keys: VecDeque<(Duration, char)>,
+    started: Instant,
+}
+
+impl ReplayedKeys {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut keys = VecDeque::new();
+        for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let key = line.split_once(' ').and_then(|(millis, key)| {
+                let mut chars = key.chars();
+                match (millis.parse().ok(), chars.next(), chars.next()) {
+                    (Some(millis), Some(key), None) => Some((Duration::from_millis(millis), key)),
+                    _ => None,
+                }
+            });
+            let Some(key) = key else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {} is not `<milliseconds> <key>`", i + 1),
+                ));
+            };
+            keys.push_back(key);
+        }
+        Ok(Self {
+            keys,
+            started: Instant::now(),
+        })
+    }
+}
+
+impl KeySource for ReplayedKeys {
+    fn next_key(&mut self, timeout: Duration) -> io::Result<Option<char>> {
+        let wait = match self.keys.front() {
+            Some((at, _)) => at.saturating_sub(self.started.elapsed()).min(timeout),
+            None => timeout,
+        };
+        if let Some(key) = read_terminal_key(wait)? {
+            return Ok(Some(key));
+        }
+        match self.keys.front() {
+            Some((at, _)) if *at <= self.started.elapsed() => {
+                Ok(self.keys.pop_front().map(|(_, key)| key))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// A tab-separated time series of how the two processes compare, with one row
+/// per throughput interval, for plotting long runs with e.g. gnuplot. This is
+/// appended to, and moved to `<path>.1` once it gets too big.
+pub struct StatsTsv {
+    path: PathBuf,
+    file: File,
+    /// The last sample of each process, to take the deltas from
+    last: Vec<Option<(i32, Sched)>>,
+}
+
+impl StatsTsv {
+    /// Roughly how big the file can get before we start a new one
+    const MAX_SIZE: u64 = 16 << 20;
+    /// The columns for each process, after the time
+    const PROCESS_COLUMNS: [&'static str; 6] = [
+        "pid",
+        "completions",
+        "runtime_ms",
+        "vruntime_ms",
+        "involuntary_switches",
+        "migrations",
+    ];
+
+    /// Open the file for a tui that shows `processes` processes
+    pub fn open(path: PathBuf, processes: usize) -> io::Result<Self> {
+        let file = Self::open_file(&path, processes)?;
+        Ok(Self {
+            path,
+            file,
+            last: vec![None; processes],
+        })
+    }
+
+    /// Open the file for appending, starting it with a header if it is new
+    fn open_file(path: &Path, processes: usize) -> io::Result<File> {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        if file.metadata()?.len() == 0 {
+            let mut header = vec![String::from("unix_time")];
+            for i in 1..=processes {
+                header.extend(Self::PROCESS_COLUMNS.map(|column| format!("{column}{i}")));
+            }
+            writeln!(file, "{}", header.join("\t"))?;
+        }
+        Ok(file)
+    }
+
+    /// Append a row for the latest interval. The deltas are `NaN` (which
+    /// gnuplot skips) until we have two samples of the same process, and once
+    /// it has exited.
+    fn append(
+        &mut self,
+        counts: &HashMap<i32, usize>,
+        scheds: &[(i32, Option<&Sched>)],
+    ) -> io::Result<()> {
+        if self.file.metadata()?.len() > Self::MAX_SIZE {
+            let mut rotated = self.path.clone().into_os_string();
+            rotated.push(".1");
+            fs::rename(&self.path, rotated)?;
+            self.file = Self::open_file(&self.path, self.last.len())?;
+        }
+
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut row = vec![format!("{:.3}", now.as_secs_f64())];
+        for (&(pid, sched), last) in scheds.iter().zip(&mut self.last) {
+            row.push(pid.to_string());
+            row.push(counts.get(&pid).unwrap_or(&0).to_string());
+            let deltas = match (sched, &last) {
+                (Some(sched), Some((last_pid, last))) if *last_pid == pid => [
+                    sched.sum_exec_runtime - last.sum_exec_runtime,
+                    sched.vruntime - last.vruntime,
+                    sched.nr_involuntary_switches as f64 - last.nr_involuntary_switches as f64,
+                    sched.nr_migrations as f64 - last.nr_migrations as f64,
+                ],
+                _ => [f64::NAN; 4],
+            };
+            // the times are in ms, and the rest are counts
+            let [runtime, vruntime, switches, migrations] = deltas;
+            row.extend([
+                format!("{runtime:.3}"),
+                format!("{vruntime:.3}"),
+                format!("{switches:.0}"),
+                format!("{migrations:.0}"),
+            ]);
+            // a process that hasn't been sampled yet has nothing to compare to
+            *last = sched
+                .filter(|sched| sched.sum_exec_runtime > 0.0)
+                .map(|sched| (pid, sched.clone()));
+        }
+        writeln!(self.file, "{}", row.join("\t"))
+    }
+}
+
+/// What we are allowed to do, which we check once at startup so that it can be
+/// shown up front rather than found out through a failed syscall
+struct Privileges {
+    cap_sys_nice: bool,
+    foreign_sched: bool,
+    /// The cpufreq governors in use, which aren't a privilege but are checked
+    /// at the same time for the same reason
+    governors: Vec<String>,
+    online_cpus: usize,
+    special_cpus: Vec<(usize, &'static str)>,
+    preempt_rt: bool,
+}
+
+impl Privileges {
+    fn check() -> Self {
+        Self {
+            cap_sys_nice: nix_ext::has_cap_sys_nice(),
+            foreign_sched: nix_ext::can_read_foreign_sched(),
+            governors: nix_ext::cpu_governors(),
+            online_cpus: nix_ext::online_cpus(),
+            special_cpus: nix_ext::special_cpus(),
+            preempt_rt: nix_ext::is_preempt_rt(),
+        }
+    }
+}
+
+/// How the tui should behave, which doesn't change while it is running
+pub struct TuiOptions {
+    /// The path of this program, so that we can respawn the workers
+    pub this: PathBuf,
+    /// Pin the tui to this CPU, so that it doesn't compete with the workers
+    /// as much
+    pub tui_cpu: Option<usize>,
+    /// Exit by ourselves once we have run for this long
+    pub max_runtime: Option<Duration>,
+    /// How often the sched panes and the log are read again
+    pub refresh: Duration,
+    pub align: Align,
+    /// What every process is compared against, if anything
+    pub baseline: Option<Sched>,
+    /// Whether to carry on when we can't parse some sched fields
+    pub tolerant_parse: bool,
+    /// Whether to show sched values exactly as /sched has them
+    pub raw_values: bool,
+    pub keys: Box<dyn KeySource>,
+    pub stats_tsv: Option<StatsTsv>,
+    /// Where snapshots are saved
+    pub snapshot_dir: PathBuf,
+    pub theme: Theme,
+}
+
+/// What a snapshot saved with `j` holds
+#[derive(serde::Serialize)]
+struct Snapshot<'a> {
+    /// When the snapshot was saved, in RFC 3339
+    timestamp: String,
+    processes: Vec<SnapshotProcess<'a>>,
+    /// The completions in the short-log, oldest first
+    recent_log: Vec<SnapshotEntry>,
+}
+
+#[derive(serde::Serialize)]
+struct SnapshotProcess<'a> {
+    pid: i32,
+    /// How many of the completions in the short-log are this process's
+    recent_completions: usize,
+    /// `None` once the process has exited
+    sched: Option<&'a Sched>,
+}
+
+#[derive(serde::Serialize)]
+struct SnapshotEntry {
+    pid: i32,
+    tid: i32,
+    /// When the task completed, in nanoseconds on the monotonic clock
+    ts: u64,
+}
+
 /// The state for out tui
 pub struct Tui {
     logfile: Log,
-    pid1: i32,
-    pid2: i32,
+    /// The path of this program, so that we can respawn the workers
+    this: PathBuf,
+    workers: Vec<Worker>,
+    /// The pid and latest sched of each worker, in the same order. The sched
+    /// is `None` once the process has exited.
+    scheds: Vec<(i32, PeriodicallyUpdate<Option<Sched>>)>,
     log_entries: PeriodicallyUpdate<VecDeque<LogEntry>>,
-    sched1: PeriodicallyUpdate<Sched>,
-    sched2: PeriodicallyUpdate<Sched>,
+    throughput: PeriodicallyUpdate<Throughput>,
+    /// Whether to show the NUMA fields, which are all 0 on a single node
+    show_numa: bool,
+    /// The unparsed /sched files of every process, if we are showing those
+    /// instead of the parsed fields
+    raw_sched: Option<Vec<String>>,
+    /// The CPUs the workers may run on, if we have pinned ourselves to a
+    /// single CPU. Respawned workers would otherwise inherit our pinning.
+    worker_cpus: Option<Vec<usize>>,
+    /// When we started, and how long we can run for before exiting by ourselves
+    started: Instant,
+    max_runtime: Option<Duration>,
+    /// How often the sched panes and the log are read again
+    refresh: Duration,
+    align: Align,
+    /// What every process is compared against, if anything
+    baseline: Option<Sched>,
+    /// Whether to carry on when we can't parse some sched fields
+    tolerant_parse: bool,
+    /// Whether to show sched values exactly as /sched has them
+    raw_values: bool,
+    privileges: Privileges,
+    /// Whether we have asked the workers that we spawned to stop logging
+    logging_paused: bool,
+    /// Whether we have stopped rereading the sched panes and the log, so that
+    /// they can be read at leisure
+    frozen: bool,
+    /// Whether to reread them once while frozen
+    step: bool,
+    /// How many of the newest completions the short-log is scrolled back
+    /// past. It only follows new completions at 0.
+    scroll: usize,
+    /// Whether `scroll` has changed since the short-log was last read
+    scrolled: bool,
+    /// Which thread of each worker to show, counting from its main thread,
+    /// if we are showing threads instead of whole processes
+    thread: Option<usize>,
+    /// The thread that each worker's pane is showing, in the same order as
+    /// the workers
+    shown_threads: Vec<Option<ShownThread>>,
+    /// What happened after the last key that did something the user can't
+    /// see on screen, e.g. where a dump was written
+    status: Option<String>,
+    keys: Box<dyn KeySource>,
+    stats_tsv: Option<StatsTsv>,
+    snapshot_dir: PathBuf,
+    theme: Theme,
 }
 
 #[derive(Debug)]
@@ -66,6 +907,12 @@ pub enum TuiError {
     Io(io::Error),
     LogError(LogError),
     SchedCreationError(SchedCreationError),
+    /// We could not respawn a worker
+    Spawn(String),
+    /// We could not pin the tui, or unpin a respawned worker
+    Affinity(AffinityError),
+    /// We draw to stderr, so it has to be a terminal
+    NotATerminal,
 }
 
 impl From<LogError> for TuiError {
@@ -86,38 +933,255 @@ impl From<SchedCreationError> for TuiError {
     }
 }
 
-impl ToString for TuiError {
-    fn to_string(&self) -> String {
+impl From<AffinityError> for TuiError {
+    fn from(value: AffinityError) -> Self {
+        Self::Affinity(value)
+    }
+}
+
+impl fmt::Display for TuiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(..) => write!(f, "something went wrong with the tui. probably restart"),
+            Self::LogError(err) => write!(f, "{err}"),
+            Self::SchedCreationError(err) => write!(f, "{err}"),
+            Self::Spawn(err) => write!(f, "{err}"),
+            Self::Affinity(err) => write!(f, "{err}"),
+            Self::NotATerminal => write!(f, "the tui needs stderr to be a terminal"),
+        }
+    }
+}
+
+impl Error for TuiError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
-            Self::Io(..) => String::from("something went wrong with the tui. probably restart"),
-            Self::LogError(err) => format!("{err}"),
-            Self::SchedCreationError(err) => format!("{err}"),
+            Self::Io(e) => Some(e),
+            Self::LogError(e) => Some(e),
+            Self::Affinity(e) => Some(e),
+            Self::SchedCreationError(..) | Self::Spawn(..) | Self::NotATerminal => None,
         }
     }
 }
 
 impl Tui {
-    const LOG_ENTRIES_UPDATE_FREQ: Duration = Duration::from_millis(200);
-    /// The color used to distinguish process 1 from process 2
-    const P1_COLOR: Color = Color::Rgb(255, 0, 255);
-    /// The color used to distinguish process 2 from process 1
-    const P2_COLOR: Color = Color::Yellow;
-
-    /// Format a pid as a pixel
-    fn fmt_pid_pixel(&self, pid: i32, include_text: bool) -> Span {
-        if pid == self.pid1 {
-            Span::styled(
-                if include_text { "1" } else { " " },
-                Style::default().bg(Self::P1_COLOR).fg(Color::Black),
-            )
-        } else if pid == self.pid2 {
-            Span::styled(
-                if include_text { "2" } else { " " },
-                Style::default().bg(Self::P2_COLOR).fg(Color::Black),
-            )
-        } else {
-            Span::from(if include_text { "?" } else { " " })
+    /// The longest we wait for a keypress before drawing again
+    const KEY_TIMEOUT: Duration = Duration::from_millis(250);
+    /// The length of each interval in the throughput chart
+    const THROUGHPUT_INTERVAL: Duration = Duration::from_secs(1);
+    /// The number of intervals shown in the throughput chart
+    const THROUGHPUT_HISTORY: usize = 60;
+    /// The height of the throughput chart, including borders
+    const CHART_HEIGHT: u16 = 12;
+    /// How long a process can go without completing a task before we warn that
+    /// it is not making progress. A process that is just losing out to the
+    /// other one will still complete tasks slowly, but a stopped or blocked
+    /// process won't complete any.
+    const STALL_THRESHOLD: Duration = Duration::from_secs(5);
+    /// The height of the status line at the bottom
+    const STATUS_HEIGHT: u16 = 1;
+    /// The height of the line comparing two processes, under the short-log
+    const DELTA_HEIGHT: u16 = 1;
+    /// The smallest terminal that we can lay everything out in
+    const MIN_WIDTH: u16 = 6;
+    const MIN_HEIGHT: u16 = 10;
+
+    /// Format a pid as a pixel. The workers log their pid as they see it, and
+    /// we compare it against the pid that we see for them. These are the same
+    /// because the workers are spawned in our pid namespace. A process in
+    /// another namespace that writes to the same logfile is not one of ours,
+    /// but will be mistaken for one if its pid happens to match.
+    fn fmt_pid_pixel(&self, pid: i32, include_text: bool) -> Span<'static> {
+        match self.pids().position(|other| other == pid) {
+            Some(i) => Span::styled(
+                if include_text {
+                    (i + 1).to_string()
+                } else {
+                    String::from(" ")
+                },
+                Style::default().bg(self.theme.process(i)).fg(Color::Black),
+            ),
+            None => Span::from(if include_text { "?" } else { " " }),
+        }
+    }
+
+    /// The pids of the workers, in order
+    fn pids(&self) -> impl Iterator<Item = i32> + '_ {
+        self.scheds.iter().map(|(pid, _)| *pid)
+    }
+
+    /// The status line, which shows what we are allowed to do
+    fn status_line(&self) -> Line<'static> {
+        let flag = |name: &'static str, ok: bool| {
+            let (text, color) = if ok {
+                ("yes", Color::Green)
+            } else {
+                ("no", Color::Red)
+            };
+            [
+                Span::raw(name),
+                Span::styled(text, Style::default().fg(color)),
+            ]
+        };
+        let mut spans = vec![];
+        if self.privileges.preempt_rt {
+            spans.push(Span::styled(
+                "RT kernel",
+                Style::default().fg(Color::Black).bg(Color::Magenta),
+            ));
+            spans.push(Span::raw("  "));
+        }
+        spans.extend(flag("CAP_SYS_NICE: ", self.privileges.cap_sys_nice));
+        spans.push(Span::raw("  "));
+        spans.extend(flag("foreign /sched: ", self.privileges.foreign_sched));
+        spans.push(Span::raw("  governor: "));
+        spans.push(match &self.privileges.governors[..] {
+            [] => Span::styled("n/a", Style::default().fg(Color::DarkGray)),
+            [governor] if governor == "performance" => {
+                Span::styled("performance", Style::default().fg(Color::Green))
+            }
+            governors => Span::styled(governors.join("/"), Style::default().fg(Color::Yellow)),
+        });
+        // the workers competing with each other is the point, but anything
+        // beyond that (e.g. multithreaded workers) changes the picture. We
+        // don't know how many threads a watched process has, so it counts as 1.
+        let threads: usize = self
+            .workers
+            .iter()
+            .map(|worker| match &worker.spawned {
+                Some((command, _)) => command.threads(),
+                None => 1,
+            })
+            .sum();
+        let online_cpus = self.privileges.online_cpus;
+        spans.push(Span::raw(format!(
+            "  threads/CPUs: {threads}/{online_cpus} ({:.1}x)",
+            threads as f64 / online_cpus as f64
+        )));
+        if !self.privileges.special_cpus.is_empty() {
+            let special = self
+                .privileges
+                .special_cpus
+                .iter()
+                .map(|(cpu, reason)| format!("{cpu} ({reason})"))
+                .collect::<Vec<_>>();
+            spans.push(Span::styled(
+                format!("  isolated: {}", special.join(", ")),
+                Style::default().fg(Color::Yellow),
+            ));
+        }
+        if self.logging_paused {
+            spans.push(Span::styled(
+                "  logging paused",
+                Style::default().fg(Color::Yellow),
+            ));
+        }
+        if let Some(thread) = self.thread {
+            spans.push(Span::styled(
+                format!("  thread {} (t for next)", thread + 1),
+                Style::default().fg(Color::Black).bg(Color::Yellow),
+            ));
+        }
+        if self.frozen {
+            spans.push(Span::styled(
+                "  frozen (. to step, f to resume)",
+                Style::default().fg(Color::Black).bg(Color::Cyan),
+            ));
         }
+        if let Some(status) = &self.status {
+            spans.push(Span::raw(format!("  {status}")));
+        }
+        Line::from(spans)
+    }
+
+    /// Format a scheduling policy as a short tag, e.g. `[BATCH]`
+    fn fmt_policy(policy: SchedPolicy) -> Span<'static> {
+        let color = match policy {
+            SchedPolicy::Other => Color::Green,
+            SchedPolicy::Batch => Color::Cyan,
+            SchedPolicy::Idle => Color::Gray,
+            SchedPolicy::Fifo | SchedPolicy::RoundRobin => Color::Red,
+            SchedPolicy::Deadline => Color::LightRed,
+            SchedPolicy::Unknown => Color::Reset,
+        };
+        let name = policy.to_string();
+        let name = name.strip_prefix("SCHED_").unwrap_or("UNKNOWN");
+        Span::styled(format!("[{name}]"), Style::default().fg(color))
+    }
+
+    /// A line comparing the first process with the second: how far ahead its
+    /// `se.vruntime` is, and how many times as much CPU time it has had. The
+    /// one that has had more CPU time is in the theme's `more` color, and the
+    /// other is in its `less` color.
+    fn delta_line(&self, a: &Sched, b: &Sched) -> Line<'static> {
+        let [pid_a, pid_b] = [0, 1].map(|i| self.scheds[i].0);
+        let fmt_ms = |ms: f64| match self.raw_values {
+            true => ms.to_string(),
+            false => FieldKind::Time.humanize(ms.to_string()),
+        };
+        let (color_a, color_b) = match a.sum_exec_runtime.partial_cmp(&b.sum_exec_runtime) {
+            Some(cmp::Ordering::Greater) => (self.theme.more, self.theme.less),
+            Some(cmp::Ordering::Less) => (self.theme.less, self.theme.more),
+            _ => (Color::Reset, Color::Reset),
+        };
+        let ratio = match b.sum_exec_runtime {
+            0.0 => String::from("n/a"),
+            b_runtime => format!("{:.2}x", a.sum_exec_runtime / b_runtime),
+        };
+        Line::from(vec![
+            Span::raw(" se.vruntime "),
+            self.fmt_pid_pixel(pid_a, true),
+            Span::raw("-"),
+            self.fmt_pid_pixel(pid_b, true),
+            Span::raw(format!(" {}", fmt_ms(a.vruntime - b.vruntime))),
+            Span::raw("   se.sum_exec_runtime "),
+            self.fmt_pid_pixel(pid_a, true),
+            Span::raw(" "),
+            Span::styled(fmt_ms(a.sum_exec_runtime), Style::default().fg(color_a)),
+            Span::raw(" vs "),
+            self.fmt_pid_pixel(pid_b, true),
+            Span::raw(" "),
+            Span::styled(fmt_ms(b.sum_exec_runtime), Style::default().fg(color_b)),
+            Span::raw(format!(" ({ratio})")),
+        ])
+    }
+
+    /// Build a line chart of completions per interval, with one series per
+    /// `(pid, color, points)`
+    fn throughput_chart(series: Vec<ChartSeries<'_>>) -> Chart<'_> {
+        let window =
+            Self::THROUGHPUT_INTERVAL.as_secs_f64() * (Self::THROUGHPUT_HISTORY - 1) as f64;
+        let max = series
+            .iter()
+            .flat_map(|(_, _, points)| points.iter().map(|&(_, y)| y))
+            .fold(1.0, f64::max);
+
+        let datasets = series
+            .into_iter()
+            .map(|(pid, color, points)| {
+                Dataset::default()
+                    .name(format!("Proc-{pid}"))
+                    .marker(Marker::Braille)
+                    .graph_type(GraphType::Line)
+                    .style(Style::default().fg(color))
+                    .data(points)
+            })
+            .collect();
+
+        Chart::new(datasets)
+            .block(Block::default().borders(Borders::all()).title(format!(
+                "Throughput (completions per {}s)",
+                Self::THROUGHPUT_INTERVAL.as_secs_f64()
+            )))
+            .x_axis(Axis::default().bounds([-window, 0.0]).labels(vec![
+                Span::raw(format!("-{window}s")),
+                Span::raw(format!("-{}s", window / 2.0)),
+                Span::raw("now"),
+            ]))
+            .y_axis(
+                Axis::default()
+                    .bounds([0.0, max])
+                    .labels(vec![Span::raw("0"), Span::raw(format!("{max}"))]),
+            )
     }
 
     fn draw(&mut self, terminal: &mut Terminal) -> Result<(), TuiError> {
@@ -127,14 +1191,42 @@ impl Tui {
             .iter()
             .map(|entry| self.fmt_pid_pixel(entry.pid, false))
             .collect::<Vec<_>>();
+        let throughput = self
+            .pids()
+            .map(|pid| self.throughput.val.points(pid, Self::THROUGHPUT_INTERVAL))
+            .collect::<Vec<_>>();
 
         terminal.draw(|f| {
-            if f.size().width < 6 || f.size().height < 10 {
+            // some terminals report a size of 0x0, e.g. while being resized
+            if f.size().area() == 0 {
+                return;
+            }
+            if f.size().width < Self::MIN_WIDTH || f.size().height < Self::MIN_HEIGHT {
+                let message = Paragraph::new("terminal too small").wrap(Wrap { trim: true });
+                f.render_widget(message, f.size());
                 return;
             }
 
-            // draw the short-log section 
-            let logs_block = Block::default().borders(Borders::all()).title("Short-Log");
+            // draw the short-log section
+            let logs_block = Block::default().borders(Borders::all()).title({
+                let mut legend = vec![Span::raw("Short-Log ")];
+                if self.scroll > 0 {
+                    legend.push(Span::styled(
+                        format!("({} back, any key for live) ", self.scroll),
+                        Style::default().fg(Color::Black).bg(Color::Cyan),
+                    ));
+                }
+                for (pid, sched) in &self.scheds {
+                    legend.push(self.fmt_pid_pixel(*pid, true));
+                    legend.push(Span::raw(" "));
+                    legend.push(match &sched.val {
+                        Some(sched) => Self::fmt_policy(sched.policy),
+                        None => Span::styled("[EXITED]", Style::default().fg(Color::DarkGray)),
+                    });
+                    legend.push(Span::raw(" "));
+                }
+                Line::from(legend)
+            });
             let logs_block_rect = {
                 let mut rect = f.size();
                 rect.height = 3;
@@ -146,92 +1238,305 @@ impl Tui {
             let logs_para_rect = logs_block_rect.inner(&Margin::new(1, 1));
             f.render_widget(logs_para, logs_para_rect);
 
-            // draw the processes
+            // draw the comparison, which only makes sense for two processes
+            let delta_height = match &self.scheds[..] {
+                [(_, a), (_, b)] => match (&a.val, &b.val) {
+                    (Some(a), Some(b)) => {
+                        let delta_rect = Rect {
+                            y: logs_block_rect.y + logs_block_rect.height,
+                            height: Self::DELTA_HEIGHT,
+                            ..f.size()
+                        };
+                        f.render_widget(Paragraph::new(self.delta_line(a, b)), delta_rect);
+                        Self::DELTA_HEIGHT
+                    }
+                    _ => 0,
+                },
+                _ => 0,
+            };
+
+            // only draw the chart if the processes still have some room
             let fsize = f.size();
-            let build_sched_widget = |pid, sched: Sched| {
-                let mut rect = logs_block_rect;
-                rect.y += logs_block_rect.height;
-                rect.width = fsize.width / 2;
-                rect.height = fsize.height - logs_block_rect.height;
-                let para = sched.as_para(rect.width as usize - 2);
-                let block = Block::default().borders(Borders::all()).title({
-                    let content = format!("Proc-{pid}");
-                    let color = if pid == self.pid1 {
-                        Self::P1_COLOR
-                    } else {
-                        Self::P2_COLOR
-                    };
-                    Span::styled(content, Style::default().fg(color))
-                });
-                (para, block, rect)
+            let chart_height = if fsize.height
+                >= logs_block_rect.height + Self::CHART_HEIGHT + Self::STATUS_HEIGHT + 10
+            {
+                Self::CHART_HEIGHT
+            } else {
+                0
             };
 
-            let (sched1_para, sched1_block, sched1_block_rect) =
-                build_sched_widget(self.pid1, self.sched1.val);
-            f.render_widget(sched1_block, sched1_block_rect);
-            f.render_widget(sched1_para, sched1_block_rect.inner(&Margin::new(1, 1)));
-
-            let (sched2_para, sched2_block, mut sched2_block_rect) =
-                build_sched_widget(self.pid2, self.sched2.val);
-            sched2_block_rect.x += sched2_block_rect.width;
-            // fill extra space to the right if it exists
-            if fsize.width % 2 == 1 {
-                sched2_block_rect.width += 1;
+            // draw the processes side by side, with any extra space going to
+            // the last one
+            let pane_width = fsize.width / self.scheds.len() as u16;
+            // the sparklines share a scale, so that they can be compared
+            let sparkline_max = self
+                .throughput
+                .val
+                .history
+                .values()
+                .flatten()
+                .copied()
+                .max()
+                .unwrap_or(0)
+                .max(1);
+            let now = Instant::now();
+            for (i, (pid, sched)) in self.scheds.iter().enumerate() {
+                let rect = Rect {
+                    x: logs_block_rect.x + pane_width * i as u16,
+                    y: logs_block_rect.y + logs_block_rect.height + delta_height,
+                    width: if i + 1 == self.scheds.len() {
+                        fsize.width - pane_width * i as u16
+                    } else {
+                        pane_width
+                    },
+                    height: fsize.height
+                        - logs_block_rect.height
+                        - delta_height
+                        - chart_height
+                        - Self::STATUS_HEIGHT,
+                };
+                let is_watched = self
+                    .workers
+                    .iter()
+                    .any(|worker| worker.pid() == *pid && worker.is_watched());
+                let sched = sched.val.as_ref();
+                // a watched process doesn't log its progress, and one that has
+                // exited is expected not to
+                let stalled_for = self
+                    .throughput
+                    .val
+                    .stalled_for(*pid, now, Self::STALL_THRESHOLD)
+                    .filter(|_| !is_watched && sched.is_some());
+                let history = self.throughput.val.history.get(pid);
+                let pane = SchedPane {
+                    pid: *pid,
+                    thread: self.shown_threads[i],
+                    color: self.theme.process(i),
+                    sched,
+                    raw_sched: self.raw_sched.as_ref().map(|raw_sched| &raw_sched[i][..]),
+                    is_watched,
+                    stalled_for,
+                    throughput: Some((
+                        history.into_iter().flatten().copied().collect(),
+                        sparkline_max,
+                    )),
+                    show_numa: self.show_numa,
+                    align: self.align,
+                    baseline: self.baseline.as_ref(),
+                    preempt_rt: self.privileges.preempt_rt,
+                    raw_values: self.raw_values,
+                    theme: &self.theme,
+                };
+                f.render_widget(pane, rect);
+            }
+
+            // draw the throughput chart
+            if chart_height > 0 {
+                let chart_rect = Rect {
+                    y: fsize.height - chart_height - Self::STATUS_HEIGHT,
+                    height: chart_height,
+                    ..fsize
+                };
+                let series = self
+                    .pids()
+                    .zip(&throughput)
+                    .enumerate()
+                    .map(|(i, (pid, points))| (pid, self.theme.process(i), &points[..]))
+                    .collect();
+                f.render_widget(Self::throughput_chart(series), chart_rect);
             }
-            f.render_widget(sched2_block, sched2_block_rect);
-            f.render_widget(sched2_para, sched2_block_rect.inner(&Margin::new(1, 1)));
+
+            // draw the status line
+            let status_rect = Rect {
+                y: fsize.height - Self::STATUS_HEIGHT,
+                height: Self::STATUS_HEIGHT,
+                ..fsize
+            };
+            f.render_widget(Paragraph::new(self.status_line()), status_rect);
         })?;
 
         Ok(())
     }
 
     fn run(&mut self) -> Result<(), TuiError> {
-        let mut terminal = Self::init_terminal()?;
+        let timed_out = Self::with_terminal(|terminal| self.event_loop(terminal));
+        // however the loop ended, the workers mustn't outlive us
+        self.stop_workers();
+        if let Some(max_runtime) = timed_out? {
+            println!(
+                "{}",
+                format_note!(
+                    "stopped after reaching --max-runtime of {}",
+                    humantime::format_duration(max_runtime)
+                )
+            );
+        }
+        Ok(())
+    }
 
+    /// Draw and handle keys until the user quits, or we reach the max
+    /// runtime, which is returned if we did
+    fn event_loop(&mut self, terminal: &mut Terminal) -> Result<Option<Duration>, TuiError> {
+        let mut timed_out = None;
         loop {
             let now = Instant::now();
+            // while frozen, only a step rereads anything
+            let step = std::mem::take(&mut self.step);
+            let scrolled = std::mem::take(&mut self.scrolled);
+            let shown = (terminal.get_frame().size().width as usize).saturating_sub(2);
 
-            if self.log_entries.should_update(now) {
-                self.log_entries.val = self.logfile.read_entries(
-                    (terminal.get_frame().size().width as usize)
-                        .checked_sub(2)
-                        .unwrap_or(0),
-                )?;
+            let following = !self.frozen && self.scroll == 0;
+            if step || scrolled || (following && self.log_entries.should_update(now)) {
+                let logs = self.logs();
+                let mut entries = Log::read_entries_merged(&logs, self.scroll, shown)?;
+                // we have scrolled back past the oldest completion, so show
+                // the oldest ones instead
+                if entries.len() < shown && self.scroll > 0 {
+                    self.scroll = (self.scroll + entries.len()).saturating_sub(shown);
+                    entries = Log::read_entries_merged(&logs, self.scroll, shown)?;
+                }
+                self.log_entries.val = entries;
             }
 
-            if self.sched1.should_update(now) {
-                self.sched1.val = Sched::of(self.pid1)?;
+            if self.throughput.should_update(now) {
+                let logs = self.logs();
+                let log_offsets = &mut self.throughput.val.log_offsets;
+                log_offsets.resize(logs.len(), 0);
+                let mut counts = HashMap::new();
+                for (log, log_offset) in logs.iter().zip(log_offsets.iter_mut()) {
+                    let (entries, new_offset) = log.read_entries_from(*log_offset)?;
+                    *log_offset = new_offset;
+                    for (pid, count) in log::count_by_pid(&entries) {
+                        *counts.entry(pid).or_insert(0) += count;
+                    }
+                    for (tid, count) in log::count_by_tid(&entries) {
+                        *self.throughput.val.by_tid.entry(tid).or_insert(0) += count;
+                    }
+                }
+                let pids = self.pids().collect::<Vec<_>>();
+                self.throughput
+                    .val
+                    .record(&counts, &pids, Self::THROUGHPUT_HISTORY, now);
+                // the scheds aren't being reread, so the deltas would be wrong.
+                // The gap shows up in the times instead.
+                if let Some(stats_tsv) = self.stats_tsv.as_mut().filter(|_| !self.frozen) {
+                    let scheds = self
+                        .scheds
+                        .iter()
+                        .map(|(pid, sched)| (*pid, sched.val.as_ref()))
+                        .collect::<Vec<_>>();
+                    if let Err(e) = stats_tsv.append(&counts, &scheds) {
+                        self.status = Some(format!("could not write to --stats-tsv: {e}"));
+                    }
+                }
             }
 
-            if self.sched2.should_update(now) {
-                self.sched2.val = Sched::of(self.pid2)?;
+            // the raw files can always be shown, even if we can't parse them
+            for (i, ((pid, sched), worker)) in
+                self.scheds.iter_mut().zip(&mut self.workers).enumerate()
+            {
+                if step || (!self.frozen && sched.should_update(now)) {
+                    let thread = self.thread.and_then(|index| worker.thread(index));
+                    self.shown_threads[i] = thread.map(|(tid, index, count)| ShownThread {
+                        tid,
+                        index,
+                        count,
+                        completions: self.throughput.val.by_tid.get(&tid).copied().unwrap_or(0),
+                    });
+                    let raw = match thread {
+                        Some((tid, ..)) => Sched::read_raw_thread(*pid, tid),
+                        None => Sched::read_raw(*pid),
+                    };
+                    match (&mut self.raw_sched, thread) {
+                        (Some(raw_sched), _) => match raw {
+                            Ok(raw) => raw_sched[i] = raw,
+                            Err(..) if worker.has_exited() => sched.val = None,
+                            Err(e) => return Err(e.into()),
+                        },
+                        (None, Some((tid, ..))) => {
+                            sched.val = worker.thread_sched(tid, self.tolerant_parse)?
+                        }
+                        (None, None) => sched.val = worker.sched(self.tolerant_parse)?,
+                    }
+                }
             }
 
-            self.draw(&mut terminal)?;
+            self.draw(terminal)?;
 
-            if crossterm::event::poll(std::time::Duration::from_millis(250))? {
-                // If a key event occurs, handle it
-                if let crossterm::event::Event::Key(key) = crossterm::event::read()? {
-                    if key.kind == crossterm::event::KeyEventKind::Press {
-                        match key.code {
-                            crossterm::event::KeyCode::Char('q') => break,
-                            _ => {}
+            // don't sleep through the next refresh
+            let timeout = self.refresh.min(Self::KEY_TIMEOUT);
+            if let Some(key) = self.keys.next_key(timeout)? {
+                // any other key goes back to following the newest completions
+                if self.scroll > 0 && key != SCROLL_BACK_KEY && key != SCROLL_FORWARD_KEY {
+                    self.scroll = 0;
+                    self.scrolled = true;
+                }
+                // half of what is shown, so that some of it stays on screen
+                let scroll_by = (shown / 2).max(1);
+                match key {
+                    'q' => break,
+                    'R' => self.restart()?,
+                    SCROLL_BACK_KEY => {
+                        self.scroll += scroll_by;
+                        self.scrolled = true;
+                    }
+                    SCROLL_FORWARD_KEY => {
+                        self.scroll = self.scroll.saturating_sub(scroll_by);
+                        self.scrolled = true;
+                    }
+                    'n' => self.show_numa = !self.show_numa,
+                    'r' => {
+                        self.raw_sched = match self.raw_sched {
+                            Some(..) => None,
+                            None => Some(vec![String::new(); self.scheds.len()]),
                         }
                     }
+                    'p' => self.toggle_logging(),
+                    'f' => self.frozen = !self.frozen,
+                    't' => self.next_thread(),
+                    // stepping while running freezes first
+                    '.' if self.frozen => self.step = true,
+                    '.' => self.frozen = true,
+                    's' | 'j' => {
+                        self.status = Some(match self.save_snapshot() {
+                            Ok(path) => format!("saved to {}", path.display()),
+                            Err(e) => format!("could not save: {e}"),
+                        })
+                    }
+                    _ => {}
+                }
+            }
+
+            if let Some(max_runtime) = self.max_runtime {
+                if self.started.elapsed() >= max_runtime {
+                    timed_out = Some(max_runtime);
+                    break;
                 }
             }
         }
 
-        self.stop_workers();
-        Self::reset_terminal()?;
-        Ok(())
+        Ok(timed_out)
+    }
+
+    /// Fail if there is no terminal to draw on. [`Tui::start`] checks this
+    /// too, but by then the floods have been spawned, so check first.
+    pub(crate) fn check_terminal() -> Result<(), TuiError> {
+        match io::stderr().is_terminal() {
+            true => Ok(()),
+            false => Err(TuiError::NotATerminal),
+        }
     }
 
     /// Boilerplate for initialising a crossterm terminal -- as recommended by
     /// the docs.
     fn init_terminal() -> Result<Terminal, TuiError> {
+        Self::check_terminal()?;
         crossterm::terminal::enable_raw_mode()?;
-        crossterm::execute!(std::io::stderr(), crossterm::terminal::EnterAlternateScreen)?;
+        crossterm::execute!(
+            std::io::stderr(),
+            crossterm::terminal::EnterAlternateScreen,
+            crossterm::event::EnableMouseCapture
+        )?;
         let terminal = Terminal::new(CrosstermBackend::new(std::io::stderr()))?;
         Ok(terminal)
     }
@@ -239,25 +1544,471 @@ impl Tui {
     /// Boilerplate for resetting terminal on application exit -- as recommended
     /// by the docs.
     fn reset_terminal() -> Result<(), TuiError> {
-        crossterm::execute!(std::io::stderr(), crossterm::terminal::LeaveAlternateScreen)?;
+        crossterm::execute!(
+            std::io::stderr(),
+            crossterm::event::DisableMouseCapture,
+            crossterm::terminal::LeaveAlternateScreen
+        )?;
         crossterm::terminal::disable_raw_mode()?;
         Ok(())
     }
 
-    fn stop_workers(&self) {
-        _ = unsafe { kill(self.pid1, SIGTERM) };
-        _ = unsafe { kill(self.pid2, SIGTERM) };
+    /// Run `f` on the terminal, and reset the terminal afterwards however `f`
+    /// returns. An error from `f` is the one that is returned.
+    fn with_terminal<R>(
+        f: impl FnOnce(&mut Terminal) -> Result<R, TuiError>,
+    ) -> Result<R, TuiError> {
+        let mut terminal = Self::init_terminal()?;
+        let result = f(&mut terminal);
+        let reset = Self::reset_terminal();
+        let val = result?;
+        reset?;
+        Ok(val)
+    }
+
+    /// The logfiles that the workers are logging to
+    fn logs(&self) -> Vec<Log> {
+        let worker_logs = self.workers.iter().filter_map(|worker| worker.log.clone());
+        let mut logs = worker_logs.collect::<Vec<_>>();
+        if logs.len() < self.workers.len() {
+            logs.push(self.logfile.clone());
+        }
+        logs
+    }
+
+    fn stop_workers(&mut self) {
+        for worker in &mut self.workers {
+            worker.stop();
+        }
     }
 
-    pub fn start(pid1: i32, pid2: i32, logfile: Log) -> Result<(), TuiError> {
+    /// Run the experiment again from scratch: stop the workers, reset the
+    /// logfile, respawn the workers and forget everything we have recorded
+    fn restart(&mut self) -> Result<(), TuiError> {
+        self.stop_workers();
+        self.logfile.reset()?;
+        for log in self.workers.iter().filter_map(|worker| worker.log.as_ref()) {
+            log.remove()?;
+        }
+        for worker in &mut self.workers {
+            let Some((command, _)) = &worker.spawned else {
+                continue;
+            };
+            // the worker pins itself if it was asked to
+            let pinned = command.cpu_affinity.is_some();
+            *worker = Worker::spawn(command.clone(), &self.this).map_err(TuiError::Spawn)?;
+            if let (Some(cpus), false) = (&self.worker_cpus, pinned) {
+                nix_ext::set_affinity(worker.pid(), cpus)?;
+            }
+        }
+        for ((pid, sched), worker) in self.scheds.iter_mut().zip(&self.workers) {
+            *pid = worker.pid();
+            sched.val = Some(Sched::default());
+        }
+
+        self.log_entries.val.clear();
+        self.throughput.val = Throughput::default();
+        self.logging_paused = false;
+        self.frozen = false;
+        Ok(())
+    }
+
+    /// Show the next thread of each worker, or go back to showing whole
+    /// processes once we have shown every thread of the worker with the most
+    fn next_thread(&mut self) {
+        let most_threads = self
+            .workers
+            .iter()
+            .filter_map(|worker| nix_ext::thread_ids(worker.pid()).ok())
+            .map(|tids| tids.len())
+            .max()
+            .unwrap_or(0);
+        self.thread = match self.thread {
+            None => Some(0),
+            Some(thread) if thread + 1 < most_threads => Some(thread + 1),
+            Some(..) => None,
+        };
+        if self.thread.is_none() {
+            self.shown_threads.fill(None);
+        }
+        // show it straight away, even if we are frozen
+        self.step = true;
+    }
+
+    /// Pause or resume logging in the workers that we spawned, without
+    /// stopping their work. Workers that we can't signal are named in the
+    /// status line.
+    fn toggle_logging(&mut self) {
+        let mut failed = vec![];
+        for worker in self
+            .workers
+            .iter_mut()
+            .filter(|worker| !worker.is_watched())
+        {
+            // an exited worker has been reaped, so its pid could be another
+            // process's by now. One that exits after this isn't reaped until
+            // we next look, so it is still safe to signal.
+            if worker.has_exited() {
+                continue;
+            }
+            if let Err(e) = nix_ext::toggle_logging(worker.pid()) {
+                failed.push(format!("{} ({e})", worker.pid()));
+            }
+        }
+        self.logging_paused = !self.logging_paused;
+        if !failed.is_empty() {
+            self.status = Some(format!("could not signal {}", failed.join(", ")));
+        }
+    }
+
+    /// Write what we are showing for every process, and the completions in
+    /// the short-log, to a new JSON file in the snapshot directory, named by
+    /// the current time, and return its path
+    fn save_snapshot(&self) -> io::Result<PathBuf> {
+        let now = SystemTime::now();
+        fs::create_dir_all(&self.snapshot_dir)?;
+        let name = format!("testnice-{}", util::compact_timestamp(now));
+        let mut path = self.snapshot_dir.join(format!("{name}.json"));
+        let mut n = 1;
+        while path.exists() {
+            path = self.snapshot_dir.join(format!("{name}-{n}.json"));
+            n += 1;
+        }
+
+        let entries = self.log_entries.val.iter().cloned().collect::<Vec<_>>();
+        let counts = log::count_by_pid(&entries);
+        let snapshot = Snapshot {
+            timestamp: humantime::format_rfc3339_seconds(now).to_string(),
+            processes: self
+                .scheds
+                .iter()
+                .map(|(pid, sched)| SnapshotProcess {
+                    pid: *pid,
+                    recent_completions: counts.get(pid).copied().unwrap_or(0),
+                    sched: sched.val.as_ref(),
+                })
+                .collect(),
+            recent_log: entries
+                .iter()
+                .map(|entry| SnapshotEntry {
+                    pid: entry.pid,
+                    tid: entry.tid,
+                    ts: entry.timestamp,
+                })
+                .collect(),
+        };
+        let mut json = serde_json::to_string(&snapshot).map_err(io::Error::from)?;
+        json.push('\n');
+        fs::write(&path, json)?;
+        Ok(path)
+    }
+
+    /// Run the tui until the user quits
+    pub fn start(
+        mut workers: Vec<Worker>,
+        logfile: Log,
+        options: TuiOptions,
+    ) -> Result<(), TuiError> {
+        let TuiOptions {
+            this,
+            tui_cpu,
+            max_runtime,
+            refresh,
+            align,
+            baseline,
+            tolerant_parse,
+            raw_values,
+            keys,
+            stats_tsv,
+            snapshot_dir,
+            theme,
+        } = options;
+        // the workers have already been spawned, so they don't inherit this
+        let worker_cpus = match tui_cpu {
+            Some(cpu) => {
+                let pinned = nix_ext::get_affinity(0)
+                    .and_then(|cpus| nix_ext::set_affinity(0, &[cpu]).map(|_| cpus));
+                match pinned {
+                    Ok(cpus) => Some(cpus),
+                    Err(e) => {
+                        workers.iter_mut().for_each(Worker::stop);
+                        return Err(e.into());
+                    }
+                }
+            }
+            None => None,
+        };
+        let count = workers.len();
         Tui {
             logfile,
-            pid1,
-            pid2,
-            log_entries: PeriodicallyUpdate::new(Self::LOG_ENTRIES_UPDATE_FREQ),
-            sched1: PeriodicallyUpdate::new(Self::LOG_ENTRIES_UPDATE_FREQ),
-            sched2: PeriodicallyUpdate::new(Self::LOG_ENTRIES_UPDATE_FREQ),
+            this,
+            scheds: workers
+                .iter()
+                .map(|worker| {
+                    // nothing has been read yet, but the process is running
+                    let mut sched = PeriodicallyUpdate::new(refresh);
+                    sched.val = Some(Sched::default());
+                    (worker.pid(), sched)
+                })
+                .collect(),
+            workers,
+            log_entries: PeriodicallyUpdate::new(refresh),
+            throughput: PeriodicallyUpdate::new(Self::THROUGHPUT_INTERVAL),
+            show_numa: nix_ext::numa_node_count() > 1,
+            raw_sched: None,
+            worker_cpus,
+            started: Instant::now(),
+            max_runtime,
+            refresh,
+            align,
+            baseline,
+            tolerant_parse,
+            raw_values,
+            privileges: Privileges::check(),
+            logging_paused: false,
+            frozen: false,
+            step: false,
+            scroll: 0,
+            scrolled: false,
+            thread: None,
+            shown_threads: vec![None; count],
+            status: None,
+            keys,
+            stats_tsv,
+            snapshot_dir,
+            theme,
         }
         .run()
     }
 }
+
+/// A single pane showing the sched of one existing process, for when there is
+/// nothing to compare it against. We never own the process, so quitting leaves
+/// it running.
+pub struct Watch {
+    worker: Worker,
+    /// The process's latest sched, which is `None` once it has exited
+    sched: PeriodicallyUpdate<Option<Sched>>,
+    /// The unparsed /sched file, if we are showing that instead of the parsed
+    /// fields
+    raw_sched: Option<String>,
+    /// Whether to show the NUMA fields, which are all 0 on a single node
+    show_numa: bool,
+    /// How often the sched is read again
+    refresh: Duration,
+    align: Align,
+    /// Whether to carry on when we can't parse some sched fields
+    tolerant_parse: bool,
+    /// Whether to show sched values exactly as /sched has them
+    raw_values: bool,
+    preempt_rt: bool,
+    /// Whether we have stopped rereading the sched, so that it can be read at
+    /// leisure
+    frozen: bool,
+    /// Whether to reread it once while frozen
+    step: bool,
+    keys: Box<dyn KeySource>,
+    theme: Theme,
+}
+
+impl Watch {
+    /// The status line, which lists the keys, since there is little else to
+    /// say
+    fn status_line(&self) -> Line<'static> {
+        let mut spans = vec![Span::styled(
+            "q quit  n NUMA fields  r raw /sched  f freeze  s step",
+            Style::default().fg(Color::DarkGray),
+        )];
+        if self.frozen {
+            spans.push(Span::styled(
+                "  frozen (. to step, f to resume)",
+                Style::default().fg(Color::Black).bg(Color::Cyan),
+            ));
+        }
+        Line::from(spans)
+    }
+
+    fn draw(&self, terminal: &mut Terminal) -> Result<(), TuiError> {
+        terminal.draw(|f| {
+            // some terminals report a size of 0x0, e.g. while being resized
+            if f.size().area() == 0 {
+                return;
+            }
+            if f.size().width < Tui::MIN_WIDTH || f.size().height < Tui::MIN_HEIGHT {
+                let message = Paragraph::new("terminal too small").wrap(Wrap { trim: true });
+                f.render_widget(message, f.size());
+                return;
+            }
+
+            let fsize = f.size();
+            let pane = SchedPane {
+                pid: self.worker.pid(),
+                thread: None,
+                color: self.theme.process(0),
+                sched: self.sched.val.as_ref(),
+                raw_sched: self.raw_sched.as_deref(),
+                is_watched: true,
+                stalled_for: None,
+                throughput: None,
+                show_numa: self.show_numa,
+                align: self.align,
+                baseline: None,
+                preempt_rt: self.preempt_rt,
+                raw_values: self.raw_values,
+                theme: &self.theme,
+            };
+            let pane_rect = Rect {
+                height: fsize.height - Tui::STATUS_HEIGHT,
+                ..fsize
+            };
+            f.render_widget(pane, pane_rect);
+
+            let status_rect = Rect {
+                y: fsize.height - Tui::STATUS_HEIGHT,
+                height: Tui::STATUS_HEIGHT,
+                ..fsize
+            };
+            f.render_widget(Paragraph::new(self.status_line()), status_rect);
+        })?;
+
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<(), TuiError> {
+        Tui::with_terminal(|terminal| self.event_loop(terminal))
+    }
+
+    /// Draw and handle keys until the user quits
+    fn event_loop(&mut self, terminal: &mut Terminal) -> Result<(), TuiError> {
+        loop {
+            let now = Instant::now();
+            // while frozen, only a step rereads anything
+            let step = std::mem::take(&mut self.step);
+
+            if step || (!self.frozen && self.sched.should_update(now)) {
+                // the raw file can always be shown, even if we can't parse it
+                match &mut self.raw_sched {
+                    Some(raw_sched) => match Sched::read_raw(self.worker.pid()) {
+                        Ok(raw) => *raw_sched = raw,
+                        Err(..) if self.worker.has_exited() => self.sched.val = None,
+                        Err(e) => return Err(e.into()),
+                    },
+                    None => self.sched.val = self.worker.sched(self.tolerant_parse)?,
+                }
+            }
+
+            self.draw(terminal)?;
+
+            // don't sleep through the next refresh
+            let timeout = self.refresh.min(Tui::KEY_TIMEOUT);
+            if let Some(key) = self.keys.next_key(timeout)? {
+                match key {
+                    // the process isn't ours, so it is left alone
+                    'q' => break,
+                    'n' => self.show_numa = !self.show_numa,
+                    'r' => {
+                        self.raw_sched = match self.raw_sched {
+                            Some(..) => None,
+                            None => Some(String::new()),
+                        }
+                    }
+                    'f' => self.frozen = !self.frozen,
+                    // stepping while running freezes first
+                    '.' if self.frozen => self.step = true,
+                    '.' => self.frozen = true,
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Watch the process `pid` until the user quits
+    pub fn start(
+        pid: i32,
+        refresh: Duration,
+        align: Align,
+        tolerant_parse: bool,
+        raw_values: bool,
+        theme: Theme,
+    ) -> Result<(), TuiError> {
+        // nothing has been read yet, but the process is running
+        let mut sched = PeriodicallyUpdate::new(refresh);
+        sched.val = Some(Sched::default());
+        Watch {
+            worker: Worker::watch(pid),
+            sched,
+            raw_sched: None,
+            show_numa: nix_ext::numa_node_count() > 1,
+            refresh,
+            align,
+            tolerant_parse,
+            raw_values,
+            preempt_rt: nix_ext::is_preempt_rt(),
+            frozen: false,
+            step: false,
+            keys: Box::new(TerminalKeys::new()),
+            theme,
+        }
+        .run()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_line_fits_narrow_widths() {
+        let mut fields = Sched::default().fields();
+        fields.push((
+            "se.avg.util_est.enqueued",
+            u64::MAX.to_string(),
+            FieldKind::Counter,
+        ));
+        fields.push(("wchan", String::from("do_epoll_wait…"), FieldKind::Other));
+        for width in 0..=48 {
+            for val_col in [0, 10, 30, 60] {
+                for (field_name, val_str, _) in &fields {
+                    let line = field_line(
+                        width,
+                        val_col,
+                        field_name,
+                        val_str.clone(),
+                        Color::Reset,
+                        false,
+                    );
+                    assert!(
+                        line.width() <= width,
+                        "{field_name} = {val_str} is {} wide, in {width}",
+                        line.width()
+                    );
+                    let text = line
+                        .spans
+                        .iter()
+                        .map(|span| &span.content[..])
+                        .collect::<String>();
+                    // the value is only cut short if it can't fit at all
+                    if val_str.chars().count() <= width {
+                        assert!(text.ends_with(&val_str[..]), "{text:?} in {width}");
+                    }
+                    // and the two are never run together
+                    if text.len() > val_str.len() && text.ends_with(&val_str[..]) {
+                        let name_end = text.len() - val_str.len();
+                        assert!(text[..name_end].ends_with(' '), "{text:?} in {width}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn truncate_adds_ellipsis() {
+        assert_eq!(truncate("se.vruntime", 20), "se.vruntime");
+        assert_eq!(truncate("se.vruntime", 11), "se.vruntime");
+        assert_eq!(truncate("se.vruntime", 6), "se.vr…");
+        assert_eq!(truncate("se.vruntime", 1), "…");
+        assert_eq!(truncate("se.vruntime", 0), "");
+    }
+}
@@ -1,7 +1,45 @@
+//! Helpers shared by the library and the `testnice` binary
+
+use std::time::SystemTime;
+
+#[doc(hidden)]
 #[macro_export]
 macro_rules! format_err {
     ($($arg:tt)*) => {{
         use owo_colors::OwoColorize;
         format!("{} {}", "error:".red().bold(), format_args!($($arg)*))
     }};
-}
\ No newline at end of file
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! format_warn {
+    ($($arg:tt)*) => {{
+        use owo_colors::OwoColorize;
+        format!("{} {}", "warning:".yellow().bold(), format_args!($($arg)*))
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! format_note {
+    ($($arg:tt)*) => {{
+        use owo_colors::OwoColorize;
+        format!("{} {}", "note:".cyan().bold(), format_args!($($arg)*))
+    }};
+}
+
+/// Format `time` (in UTC) compactly enough to go in a file name, e.g.
+/// `20240101-120000`
+pub fn compact_timestamp(time: SystemTime) -> String {
+    // 2024-01-01T12:00:00Z -> 20240101-120000
+    humantime::format_rfc3339_seconds(time)
+        .to_string()
+        .chars()
+        .filter_map(|ch| match ch {
+            'T' => Some('-'),
+            '0'..='9' => Some(ch),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,10 @@
+#!/bin/sh
+# Count the syscalls that a flood makes to log a fixed number of completions,
+# e.g. `./strace-log.sh ./target/release/nice_level 100000`
+set -eu
+bin=${1:-./target/release/nice_level}
+logfile=$(mktemp)
+trap 'rm -f "$logfile" "$logfile.strace"' EXIT
+strace -f -c -e trace=openat,close,fcntl,write,statx,fstat -o "$logfile.strace" \
+    "$bin" flood --ni=0 --steps=1 --iterations="${2:-10000}" --logfile="$logfile" > /dev/null
+cat "$logfile.strace"